@@ -0,0 +1,335 @@
+//! "Sign in with" an OAuth 1.0a provider for [axum](https://github.com/tokio-rs/axum).
+//!
+//! `SignIn::router` serves two routes:
+//!
+//! - `GET /login` obtains a request token, saves its secret in the `PendingTokenStore`,
+//!   binds the token to the browser with a signed cookie
+//!   and redirects the user to the authorization endpoint.
+//! - `GET /callback` takes the secret of the token in the cookie, checks that `oauth_token` is the same,
+//!   exchanges `oauth_verifier` for token credentials and passes them to `LoginHandler::on_login`.
+//!
+//! The cookie is signed with a key derived from the consumer secret,
+//! so that a callback started by another browser is rejected.
+//!
+//! # Example
+//! ```no_run
+//! use axum::response::{IntoResponse, Response};
+//! use oauthcli::{OAuthTokenResponse, SignatureMethod, TokenCredentials};
+//! use oauthcli::store::MemoryTokenStore;
+//! use oauthcli::url::Url;
+//! use oauthcli_axum::{LoginHandler, ProviderConfig, SignIn};
+//!
+//! struct Hello;
+//!
+//! impl LoginHandler for Hello {
+//!     async fn on_login(&self, credentials: TokenCredentials, _: OAuthTokenResponse) -> Response {
+//!         format!("Hello, {}", credentials.token).into_response()
+//!     }
+//! }
+//!
+//! # async fn run() {
+//! let config = ProviderConfig {
+//!     consumer_key: "consumer".to_owned(),
+//!     consumer_secret: "secret".into(),
+//!     signature_method: SignatureMethod::HmacSha1,
+//!     request_token_url: Url::parse("https://api.twitter.com/oauth/request_token").unwrap(),
+//!     authorize_url: Url::parse("https://api.twitter.com/oauth/authenticate").unwrap(),
+//!     access_token_url: Url::parse("https://api.twitter.com/oauth/access_token").unwrap(),
+//!     callback_url: Url::parse("https://example.com/auth/callback").unwrap(),
+//!     for_twitter: true,
+//! };
+//!
+//! let app = axum::Router::new()
+//!     .nest("/auth", SignIn::new(config, MemoryTokenStore::new(600), Hello).router());
+//! let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await.unwrap();
+//! axum::serve(listener, app).await.unwrap();
+//! # }
+//! ```
+
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use axum::extract::State;
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode, Uri};
+use axum::response::{IntoResponse, Redirect, Response};
+use axum::routing::get;
+use axum::Router;
+use oauthcli::flow::{FlowError, RequestToken};
+use oauthcli::store::PendingTokenStore;
+use oauthcli::url::percent_encoding::percent_decode;
+use oauthcli::url::Url;
+use oauthcli::{
+    crypto, signing, ConsumerSecret, OAuthAuthorizationHeader, OAuthAuthorizationHeaderBuilder,
+    OAuthCallback, OAuthTokenResponse, ParseOAuthCallbackError, ParseOAuthTokenResponseError,
+    SignatureMethod, TokenCredentials,
+};
+
+/// The cookie which holds the pending request token.
+const COOKIE_NAME: &str = "oauthcli_token";
+
+/// The client credentials and the endpoints of the provider.
+#[derive(Debug, Clone)]
+pub struct ProviderConfig {
+    /// `oauth_consumer_key`
+    pub consumer_key: String,
+    pub consumer_secret: ConsumerSecret<'static>,
+    pub signature_method: SignatureMethod,
+    /// Temporary Credential Request endpoint
+    pub request_token_url: Url,
+    /// Resource Owner Authorization endpoint
+    pub authorize_url: Url,
+    /// Token Request endpoint
+    pub access_token_url: Url,
+    /// The absolute URI of the `/callback` route, sent as `oauth_callback`.
+    pub callback_url: Url,
+    /// Signs with `finish_for_twitter` instead of `finish`.
+    pub for_twitter: bool,
+}
+
+#[derive(Debug)]
+pub enum SignInError {
+    /// Failed to send the request to the provider.
+    Http(reqwest::Error),
+    /// The provider responded with the status code other than 2xx.
+    ProviderStatus(StatusCode, String),
+    /// The response of the provider is not a token response.
+    TokenResponse(ParseOAuthTokenResponseError),
+    /// The provider does not support OAuth 1.0a.
+    Flow(FlowError),
+    /// The callback is malformed or the user denied the authorization.
+    Callback(ParseOAuthCallbackError),
+    /// The browser has no pending request token, or it has expired.
+    UnknownToken,
+    /// The `PendingTokenStore` failed.
+    Store(String),
+}
+
+impl SignInError {
+    /// The status code of the default error response.
+    pub fn status_code(&self) -> StatusCode {
+        match *self {
+            SignInError::Callback(ParseOAuthCallbackError::Denied) => StatusCode::FORBIDDEN,
+            SignInError::Callback(_) | SignInError::UnknownToken => StatusCode::BAD_REQUEST,
+            SignInError::Store(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            _ => StatusCode::BAD_GATEWAY,
+        }
+    }
+}
+
+impl fmt::Display for SignInError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SignInError::Http(ref e) => write!(f, "Failed to request the provider: {}", e),
+            SignInError::ProviderStatus(status, _) => write!(f, "The provider responded with {}", status),
+            SignInError::TokenResponse(ref e) => write!(f, "Invalid token response: {}", e),
+            SignInError::Flow(ref e) => fmt::Display::fmt(e, f),
+            SignInError::Callback(ref e) => fmt::Display::fmt(e, f),
+            SignInError::UnknownToken => f.write_str("`oauth_token` is not pending"),
+            SignInError::Store(ref e) => write!(f, "The pending token store failed: {}", e),
+        }
+    }
+}
+
+impl Error for SignInError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            SignInError::Http(ref e) => Some(e),
+            SignInError::TokenResponse(ref e) => Some(e),
+            SignInError::Flow(ref e) => Some(e),
+            SignInError::Callback(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// The application hook which receives the result of the sign-in.
+pub trait LoginHandler: Send + Sync + 'static {
+    /// Called with the token credentials of the user, and returns the response of `/callback`,
+    /// which typically starts the session and redirects.
+    /// `response` contains the provider-specific parameters such as `user_id` of Twitter.
+    fn on_login(
+        &self,
+        credentials: TokenCredentials,
+        response: OAuthTokenResponse,
+    ) -> impl Future<Output = Response> + Send;
+
+    /// Returns the response when the sign-in failed.
+    /// The default implementation responds with `SignInError::status_code`.
+    fn on_error(&self, error: SignInError) -> Response {
+        (error.status_code(), error.to_string()).into_response()
+    }
+}
+
+/// The state of the routes.
+pub struct SignIn<H, S> {
+    config: ProviderConfig,
+    store: S,
+    handler: H,
+    client: reqwest::Client,
+}
+
+impl<H, S> SignIn<H, S>
+where
+    H: LoginHandler,
+    S: PendingTokenStore + Send + Sync + 'static,
+    S::Error: fmt::Display,
+{
+    pub fn new(config: ProviderConfig, store: S, handler: H) -> Self {
+        SignIn { config, store, handler, client: reqwest::Client::new() }
+    }
+
+    /// Replaces the HTTP client to request the provider.
+    pub fn with_client(mut self, client: reqwest::Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Creates the router with `/login` and `/callback`.
+    pub fn router(self) -> Router {
+        Router::new()
+            .route("/login", get(login::<H, S>))
+            .route("/callback", get(callback::<H, S>))
+            .with_state(Arc::new(self))
+    }
+
+    fn finish(&self, builder: &OAuthAuthorizationHeaderBuilder) -> OAuthAuthorizationHeader {
+        if self.config.for_twitter { builder.finish_for_twitter() } else { builder.finish() }
+    }
+
+    async fn post(&self, url: &Url, authorization: OAuthAuthorizationHeader) -> Result<OAuthTokenResponse, SignInError> {
+        let res = self.client.post(url.as_str())
+            .header(header::AUTHORIZATION, authorization.to_string())
+            .send()
+            .await
+            .map_err(SignInError::Http)?;
+
+        let status = res.status();
+        let body = res.text().await.map_err(SignInError::Http)?;
+        if !status.is_success() {
+            return Err(SignInError::ProviderStatus(status, body));
+        }
+
+        OAuthTokenResponse::from_body(&body).map_err(SignInError::TokenResponse)
+    }
+
+    async fn login(&self) -> Result<Response, SignInError> {
+        let config = &self.config;
+        let authorization = self.finish(&RequestToken::request(
+            &config.request_token_url, &config.consumer_key[..], &config.consumer_secret,
+            config.signature_method, config.callback_url.as_str()));
+        let res = self.post(&config.request_token_url, authorization).await?;
+        let request_token = RequestToken::from_response(&res).map_err(SignInError::Flow)?;
+
+        self.store.insert(request_token.token(), request_token.secret(), now())
+            .map_err(|e| SignInError::Store(e.to_string()))?;
+
+        let mut url = config.authorize_url.clone();
+        url.query_pairs_mut().append_pair("oauth_token", request_token.token());
+        let cookie = format!(
+            "{}={}.{}",
+            COOKIE_NAME,
+            signing::percent_encode(request_token.token().as_bytes()),
+            self.cookie_tag(request_token.token())
+        );
+        Ok(([(header::SET_COOKIE, self.cookie_attributes(cookie))], Redirect::to(url.as_str())).into_response())
+    }
+
+    async fn callback(&self, headers: &HeaderMap, uri: &Uri) -> Result<Response, SignInError> {
+        let config = &self.config;
+        let mut url = config.callback_url.clone();
+        url.set_query(uri.query());
+        let callback = OAuthCallback::from_url(&url);
+
+        // The pending token is taken even if the user denied the authorization
+        let pending = self.pending_token(headers);
+        let secret = match pending {
+            Some(ref token) => self.store.take(token, now()).map_err(|e| SignInError::Store(e.to_string()))?,
+            None => None,
+        };
+        let callback = callback.map_err(SignInError::Callback)?;
+        let request_token = match (pending, secret) {
+            (Some(token), Some(secret)) => RequestToken::from_confirmed_parts(token, secret),
+            _ => return Err(SignInError::UnknownToken),
+        };
+
+        let authorized = request_token.authorize(&callback).map_err(|e| match e {
+            FlowError::CallbackError(e) => SignInError::Callback(e),
+            e => SignInError::Flow(e),
+        })?;
+        let authorization = self.finish(&authorized.access_token_request(
+            &config.access_token_url, &config.consumer_key[..], &config.consumer_secret,
+            config.signature_method));
+        let res = self.post(&config.access_token_url, authorization).await?;
+
+        let credentials = authorized.into_access_token(&res).into_credentials();
+        Ok(self.handler.on_login(credentials, res).await)
+    }
+
+    /// HMAC-SHA256 of `token` in hex, with the key derived from the consumer secret.
+    fn cookie_tag(&self, token: &str) -> String {
+        let key = crypto::hmac_sha256(self.config.consumer_secret.expose().as_bytes(), COOKIE_NAME.as_bytes());
+        crypto::hmac_sha256(&key, token.as_bytes()).iter().map(|x| format!("{:02x}", x)).collect()
+    }
+
+    /// The request token in the cookie if the tag is valid.
+    fn pending_token(&self, headers: &HeaderMap) -> Option<String> {
+        let value = headers.get_all(header::COOKIE).iter()
+            .filter_map(|x| x.to_str().ok())
+            .flat_map(|x| x.split(';'))
+            .filter_map(|x| x.trim().split_once('='))
+            .find(|&(k, _)| k == COOKIE_NAME)?
+            .1;
+        let (token, tag) = value.rsplit_once('.')?;
+        let token = percent_decode(token.as_bytes()).decode_utf8().ok()?.into_owned();
+
+        if crypto::constant_time_eq(self.cookie_tag(&token).as_bytes(), tag.as_bytes()) {
+            Some(token)
+        } else {
+            None
+        }
+    }
+
+    /// Adds the attributes to `cookie`, limiting it to the callback path.
+    fn cookie_attributes(&self, cookie: String) -> HeaderValue {
+        let callback_url = &self.config.callback_url;
+        let secure = if callback_url.scheme() == "https" { "; Secure" } else { "" };
+        let cookie = format!("{}; Path={}; HttpOnly; SameSite=Lax{}", cookie, callback_url.path(), secure);
+        HeaderValue::from_str(&cookie).expect("The cookie is percent-encoded")
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|x| x.as_secs()).unwrap_or(0)
+}
+
+/// Handler of `GET /login`.
+pub async fn login<H, S>(State(sign_in): State<Arc<SignIn<H, S>>>) -> Response
+where
+    H: LoginHandler,
+    S: PendingTokenStore + Send + Sync + 'static,
+    S::Error: fmt::Display,
+{
+    match sign_in.login().await {
+        Ok(x) => x,
+        Err(e) => sign_in.handler.on_error(e),
+    }
+}
+
+/// Handler of `GET /callback`, which also removes the cookie.
+pub async fn callback<H, S>(State(sign_in): State<Arc<SignIn<H, S>>>, headers: HeaderMap, uri: Uri) -> Response
+where
+    H: LoginHandler,
+    S: PendingTokenStore + Send + Sync + 'static,
+    S::Error: fmt::Display,
+{
+    let mut res = match sign_in.callback(&headers, &uri).await {
+        Ok(x) => x,
+        Err(e) => sign_in.handler.on_error(e),
+    };
+    let cookie = sign_in.cookie_attributes(format!("{}=; Max-Age=0", COOKIE_NAME));
+    res.headers_mut().append(header::SET_COOKIE, cookie);
+    res
+}
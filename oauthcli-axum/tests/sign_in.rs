@@ -0,0 +1,118 @@
+use axum::body::Body;
+use axum::http::{header, HeaderMap, Request, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Router;
+use oauthcli::store::MemoryTokenStore;
+use oauthcli::url::Url;
+use oauthcli::{OAuthAuthorizationHeaderBuilder, OAuthTokenResponse, SignatureMethod, TokenCredentials};
+use oauthcli_axum::{LoginHandler, ProviderConfig, SignIn};
+use oauthcli_mock::{MockConfig, MockProvider, MockServer};
+use tower::ServiceExt;
+
+/// Responds with the access token after checking it at the protected resource of the provider.
+struct Hook(Url);
+
+impl LoginHandler for Hook {
+    async fn on_login(&self, credentials: TokenCredentials, response: OAuthTokenResponse) -> Response {
+        let url = &self.0;
+        let authorization = OAuthAuthorizationHeaderBuilder::new("GET", url, "consumer", "consumer_secret", SignatureMethod::HmacSha1)
+            .token(&credentials.token[..], &credentials.secret)
+            .finish_for_twitter();
+        let res = reqwest::Client::new()
+            .get(url.as_str())
+            .header(header::AUTHORIZATION, authorization.to_string())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.text().await.unwrap(), format!("oauth_token={}", credentials.token));
+
+        format!("screen_name={}", response.parameter("screen_name").unwrap()).into_response()
+    }
+}
+
+async fn start(config: MockConfig) -> (MockServer, Router) {
+    let server = MockProvider::new(MockConfig { twitter_quirks: true, ..config }).start().await.unwrap();
+    let config = ProviderConfig {
+        consumer_key: "consumer".to_owned(),
+        consumer_secret: "consumer_secret".into(),
+        signature_method: SignatureMethod::HmacSha1,
+        request_token_url: server.url("/oauth/request_token"),
+        authorize_url: server.url("/oauth/authorize?force_login=true"),
+        access_token_url: server.url("/oauth/access_token"),
+        callback_url: Url::parse("http://client.example/callback").unwrap(),
+        for_twitter: true,
+    };
+    let hook = Hook(server.url("/resource"));
+    (server, SignIn::new(config, MemoryTokenStore::new(600), hook).router())
+}
+
+async fn get(app: &Router, uri: &str, cookie: Option<&str>) -> (StatusCode, HeaderMap, String) {
+    let mut req = Request::get(uri);
+    if let Some(cookie) = cookie {
+        req = req.header(header::COOKIE, cookie);
+    }
+    let res = app.clone().oneshot(req.body(Body::empty()).unwrap()).await.unwrap();
+    let status = res.status();
+    let headers = res.headers().clone();
+    let body = axum::body::to_bytes(res.into_body(), usize::MAX).await.unwrap();
+    (status, headers, String::from_utf8(body.to_vec()).unwrap())
+}
+
+/// Starts the sign-in, lets the provider authorize it, and returns the cookie and the callback URI.
+async fn login(app: &Router) -> (String, String) {
+    let (status, headers, _) = get(app, "/login", None).await;
+    assert_eq!(status, StatusCode::SEE_OTHER);
+    let cookie = headers[header::SET_COOKIE].to_str().unwrap();
+    assert!(cookie.ends_with("; Path=/callback; HttpOnly; SameSite=Lax"));
+    let cookie = cookie.split(';').next().unwrap().to_owned();
+
+    let authorize_url = headers[header::LOCATION].to_str().unwrap();
+    assert!(authorize_url.contains("/oauth/authorize?force_login=true&oauth_token="));
+    let client = reqwest::Client::builder().redirect(reqwest::redirect::Policy::none()).build().unwrap();
+    let res = client.get(authorize_url).send().await.unwrap();
+    let callback = Url::parse(res.headers()[header::LOCATION].to_str().unwrap()).unwrap();
+    assert_eq!(callback.path(), "/callback");
+
+    (cookie, format!("/callback?{}", callback.query().unwrap()))
+}
+
+#[tokio::test]
+async fn sign_in() {
+    let (_server, app) = start(MockConfig::default()).await;
+    let (cookie, callback) = login(&app).await;
+
+    // Without the cookie or with a forged one, e.g. login CSRF
+    let (status, headers, body) = get(&app, &callback, None).await;
+    assert_eq!((status, &body[..]), (StatusCode::BAD_REQUEST, "`oauth_token` is not pending"));
+    assert!(headers[header::SET_COOKIE].to_str().unwrap().starts_with("oauthcli_token=; Max-Age=0"));
+    let (status, _, _) = get(&app, &callback, Some(&format!("{}0", cookie))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (other_cookie, _) = login(&app).await;
+    let (status, _, body) = get(&app, &callback, Some(&other_cookie)).await;
+    assert_eq!((status, &body[..]), (StatusCode::BAD_REQUEST, "`oauth_token` is not the pending request token"));
+
+    let (status, _, body) = get(&app, &callback, Some(&format!("other=1; {}", cookie))).await;
+    assert_eq!((status, &body[..]), (StatusCode::OK, "screen_name=oauthcli_mock"));
+
+    // The request token is consumed
+    let (status, _, _) = get(&app, &callback, Some(&cookie)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn denied() {
+    let (_server, app) = start(MockConfig { deny: true, ..MockConfig::default() }).await;
+    let (cookie, callback) = login(&app).await;
+    assert!(callback.starts_with("/callback?denied="));
+
+    let (status, _, _) = get(&app, &callback, Some(&cookie)).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn provider_error() {
+    let (_server, app) = start(MockConfig { consumer_secret: "other_secret".to_owned(), ..MockConfig::default() }).await;
+    let (status, _, body) = get(&app, "/login", None).await;
+    assert_eq!((status, &body[..]), (StatusCode::BAD_GATEWAY, "The provider responded with 401 Unauthorized"));
+}
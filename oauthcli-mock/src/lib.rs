@@ -0,0 +1,384 @@
+//! A local OAuth 1.0a Service Provider to test clients without the real provider.
+//!
+//! | Endpoint | |
+//! |----------|-|
+//! | `POST /oauth/request_token` | Temporary Credential Request |
+//! | `GET /oauth/authorize` | Approves (or denies with `MockConfig::deny`) immediately and redirects to `oauth_callback` |
+//! | `POST /oauth/access_token` | Token Request |
+//! | `GET` or `POST /resource` | Protected resource, which responds with the access token |
+//!
+//! The signatures are verified by `oauthcli::SignedRequest`.
+//!
+//! # Example
+//! ```
+//! # #[tokio::main]
+//! # async fn main() {
+//! use oauthcli_mock::{MockConfig, MockProvider};
+//!
+//! let server = MockProvider::new(MockConfig::default()).start().await.unwrap();
+//! let request_token_url = server.url("/oauth/request_token");
+//! # }
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use axum::body::Bytes;
+use axum::extract::{Query, State};
+use axum::http::{header, HeaderMap, Method, StatusCode, Uri};
+use axum::response::{IntoResponse, Redirect, Response};
+use axum::routing::{get, post};
+use axum::Router;
+use oauthcli::url::Url;
+use oauthcli::{
+    decode_form_body, OAuthAuthorizationHeader, OAuthChallengeBuilder, OAuthProblem, OAuthProblemReport,
+    SignedRequest, VerifyError, VerifyOptions,
+};
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+
+/// Switches of the behavior of `MockProvider`.
+#[derive(Debug, Clone)]
+pub struct MockConfig {
+    /// The only consumer accepted.
+    pub consumer_key: String,
+    pub consumer_secret: String,
+    /// Verifies the signatures in the same way as `finish_for_twitter`,
+    /// and adds `user_id` and `screen_name` to the access token response like Twitter.
+    pub twitter_quirks: bool,
+    /// Seconds added to the clock of the provider, to emulate clock skew.
+    pub clock_offset: i64,
+    /// Seconds of the difference of `oauth_timestamp` accepted.
+    pub timestamp_window: u64,
+    /// Reports the errors with OAuth Problem Reporting in `WWW-Authenticate` and the body.
+    /// Otherwise responds with an empty `401 Unauthorized`.
+    pub problem_reporting: bool,
+    /// Rejects every signed request with this problem.
+    pub fail_with: Option<OAuthProblem>,
+    /// The authorization endpoint redirects with `denied` instead of `oauth_verifier`.
+    pub deny: bool,
+}
+
+impl Default for MockConfig {
+    fn default() -> MockConfig {
+        MockConfig {
+            consumer_key: "consumer".to_owned(),
+            consumer_secret: "consumer_secret".to_owned(),
+            twitter_quirks: false,
+            clock_offset: 0,
+            timestamp_window: 300,
+            problem_reporting: true,
+            fail_with: None,
+            deny: false,
+        }
+    }
+}
+
+struct PendingToken {
+    secret: String,
+    callback: String,
+    verifier: Option<String>,
+}
+
+#[derive(Default)]
+struct Tokens {
+    pending: HashMap<String, PendingToken>,
+    access: HashMap<String, String>,
+    nonces: HashSet<(u64, String)>,
+}
+
+/// Which token the endpoint requires.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Step {
+    RequestToken,
+    AccessToken,
+    Resource,
+}
+
+/// The mock Service Provider.
+pub struct MockProvider {
+    config: MockConfig,
+    tokens: Mutex<Tokens>,
+}
+
+/// `MockProvider` listening on a local port.
+#[derive(Debug)]
+pub struct MockServer {
+    addr: SocketAddr,
+}
+
+impl MockServer {
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// The URL of `path` on this server.
+    pub fn url(&self, path: &str) -> Url {
+        Url::parse(&format!("http://{}{}", self.addr, path)).unwrap()
+    }
+}
+
+fn random_string(len: usize) -> String {
+    rand::thread_rng().sample_iter(&Alphanumeric).take(len).map(char::from).collect()
+}
+
+fn unix_time() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|x| x.as_secs() as i64).unwrap_or(0)
+}
+
+fn form_response(params: &[(&str, &str)]) -> Response {
+    let body = params.iter()
+        .map(|&(k, v)| format!("{}={}", k, oauthcli::signing::percent_encode(v.as_bytes())))
+        .collect::<Vec<_>>()
+        .join("&");
+    ([(header::CONTENT_TYPE, "application/x-www-form-urlencoded")], body).into_response()
+}
+
+impl MockProvider {
+    pub fn new(config: MockConfig) -> MockProvider {
+        MockProvider { config, tokens: Mutex::new(Tokens::default()) }
+    }
+
+    /// Creates the router of the endpoints.
+    pub fn router(self) -> Router {
+        Router::new()
+            .route("/oauth/request_token", post(request_token))
+            .route("/oauth/authorize", get(authorize))
+            .route("/oauth/access_token", post(access_token))
+            .route("/resource", get(resource).post(resource))
+            .with_state(Arc::new(self))
+    }
+
+    /// Serves on `addr`.
+    pub async fn serve(self, addr: SocketAddr) -> io::Result<()> {
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        axum::serve(listener, self.router()).await
+    }
+
+    /// Serves on a free port of `127.0.0.1` in the background.
+    pub async fn start(self) -> io::Result<MockServer> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let router = self.router();
+        tokio::spawn(async move { axum::serve(listener, router).await });
+        Ok(MockServer { addr })
+    }
+
+    fn tokens(&self) -> MutexGuard<'_, Tokens> {
+        self.tokens.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn problem(&self, report: OAuthProblemReport) -> Response {
+        if !self.config.problem_reporting {
+            return StatusCode::UNAUTHORIZED.into_response();
+        }
+
+        let challenge = OAuthChallengeBuilder::new()
+            .realm("oauthcli-mock")
+            .problem_report(report.clone())
+            .finish();
+        (
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, challenge.to_string())],
+            report.to_body(),
+        ).into_response()
+    }
+
+    /// Verifies the request, and returns its protocol parameters.
+    /// The nonce is recorded only if the signature is valid.
+    #[allow(clippy::result_large_err)]
+    fn authenticate(&self, step: Step, method: &Method, uri: &Uri, headers: &HeaderMap, body: &[u8])
+        -> Result<SignedRequest, OAuthProblemReport>
+    {
+        if let Some(ref x) = self.config.fail_with {
+            return Err(OAuthProblemReport::new(x.clone()));
+        }
+
+        let header: OAuthAuthorizationHeader = headers.get(header::AUTHORIZATION)
+            .and_then(|x| x.to_str().ok())
+            .and_then(|x| x.parse().ok())
+            .ok_or_else(|| {
+                let mut report = OAuthProblemReport::new(OAuthProblem::ParameterAbsent);
+                report.parameters_absent.push("oauth_signature".to_owned());
+                report
+            })?;
+
+        let req = SignedRequest::from_header(&header).map_err(|e| {
+            let mut report = OAuthProblemReport::new(e.to_problem());
+            if let VerifyError::ParameterAbsent(x) = e {
+                report.parameters_absent.push(x.to_owned());
+            }
+            report
+        })?;
+
+        if req.consumer_key() != self.config.consumer_key {
+            return Err(OAuthProblemReport::new(OAuthProblem::ConsumerKeyUnknown));
+        }
+
+        let now = unix_time().saturating_add(self.config.clock_offset).max(0) as u64;
+        let window = self.config.timestamp_window;
+        if let Some(timestamp) = req.timestamp() {
+            if timestamp.abs_diff(now) > window {
+                let mut report = OAuthProblemReport::new(OAuthProblem::TimestampRefused);
+                report.acceptable_timestamps = Some((now.saturating_sub(window), now.saturating_add(window)));
+                return Err(report);
+            }
+        }
+
+        let tokens = self.tokens();
+        let token_secret = match (step, req.token()) {
+            (Step::RequestToken, None) => None,
+            (Step::AccessToken, Some(x)) => match tokens.pending.get(x) {
+                Some(&PendingToken { ref secret, verifier: Some(_), .. }) => Some(secret.clone()),
+                _ => return Err(OAuthProblemReport::new(OAuthProblem::TokenRejected)),
+            },
+            (Step::Resource, Some(x)) => match tokens.access.get(x) {
+                Some(secret) => Some(secret.clone()),
+                None => return Err(OAuthProblemReport::new(OAuthProblem::TokenRejected)),
+            },
+            _ => {
+                let mut report = OAuthProblemReport::new(OAuthProblem::ParameterRejected);
+                report.parameters_rejected.push("oauth_token".to_owned());
+                return Err(report);
+            }
+        };
+        drop(tokens);
+
+        let host = headers.get(header::HOST).and_then(|x| x.to_str().ok()).unwrap_or("localhost");
+        let url = Url::parse(&format!("http://{}{}", host, uri))
+            .map_err(|_| OAuthProblemReport::new(OAuthProblem::ParameterRejected))?;
+
+        let is_form = headers.get(header::CONTENT_TYPE)
+            .and_then(|x| x.to_str().ok())
+            .is_some_and(|x| x.starts_with("application/x-www-form-urlencoded"));
+        let params = if is_form { decode_form_body(body) } else { Vec::new() };
+
+        let consumer_secret = &self.config.consumer_secret[..];
+        let token_secret = token_secret.as_deref();
+        req.verify_with(VerifyOptions::new().for_twitter(self.config.twitter_quirks),
+            method.as_str(), &url, params, consumer_secret, token_secret)
+            .map_err(|e| OAuthProblemReport::new(e.to_problem()))?;
+
+        if let Some(nonce) = req.nonce() {
+            let mut tokens = self.tokens();
+            // The requests out of the window are refused by the timestamp
+            tokens.nonces.retain(|&(timestamp, _)| timestamp.abs_diff(now) <= window);
+            if !tokens.nonces.insert((req.timestamp().unwrap_or(0), nonce.to_owned())) {
+                return Err(OAuthProblemReport::new(OAuthProblem::NonceUsed));
+            }
+        }
+
+        Ok(req)
+    }
+}
+
+async fn request_token(State(provider): State<Arc<MockProvider>>, method: Method, uri: Uri, headers: HeaderMap, body: Bytes) -> Response {
+    let req = match provider.authenticate(Step::RequestToken, &method, &uri, &headers, &body) {
+        Ok(x) => x,
+        Err(e) => return provider.problem(e),
+    };
+
+    let callback = match req.parameter("oauth_callback") {
+        Some(x) => x.to_owned(),
+        None => {
+            let mut report = OAuthProblemReport::new(OAuthProblem::ParameterAbsent);
+            report.parameters_absent.push("oauth_callback".to_owned());
+            return provider.problem(report);
+        }
+    };
+
+    let token = random_string(24);
+    let secret = random_string(32);
+    provider.tokens().pending.insert(token.clone(), PendingToken { secret: secret.clone(), callback, verifier: None });
+
+    form_response(&[("oauth_token", &token), ("oauth_token_secret", &secret), ("oauth_callback_confirmed", "true")])
+}
+
+async fn authorize(State(provider): State<Arc<MockProvider>>, Query(query): Query<HashMap<String, String>>) -> Response {
+    let token = match query.get("oauth_token") {
+        Some(x) => x,
+        None => return (StatusCode::BAD_REQUEST, "`oauth_token` is not found").into_response(),
+    };
+
+    let mut tokens = provider.tokens();
+    let pending = match tokens.pending.get_mut(token) {
+        Some(x) => x,
+        None => return (StatusCode::BAD_REQUEST, "Unknown `oauth_token`").into_response(),
+    };
+
+    let mut callback = match &pending.callback[..] {
+        "oob" => None,
+        x => match Url::parse(x) {
+            Ok(x) => Some(x),
+            Err(_) => return (StatusCode::BAD_REQUEST, "Invalid `oauth_callback`").into_response(),
+        },
+    };
+
+    if provider.config.deny {
+        let token = token.clone();
+        tokens.pending.remove(&token);
+        return match callback {
+            Some(mut x) => {
+                x.query_pairs_mut().append_pair("denied", &token);
+                Redirect::to(x.as_str()).into_response()
+            }
+            None => (StatusCode::FORBIDDEN, "Denied").into_response(),
+        };
+    }
+
+    let verifier = random_string(16);
+    pending.verifier = Some(verifier.clone());
+
+    match callback.as_mut() {
+        Some(x) => {
+            x.query_pairs_mut().append_pair("oauth_token", token).append_pair("oauth_verifier", &verifier);
+            Redirect::to(x.as_str()).into_response()
+        }
+        // PIN-based authorization
+        None => verifier.into_response(),
+    }
+}
+
+async fn access_token(State(provider): State<Arc<MockProvider>>, method: Method, uri: Uri, headers: HeaderMap, body: Bytes) -> Response {
+    let req = match provider.authenticate(Step::AccessToken, &method, &uri, &headers, &body) {
+        Ok(x) => x,
+        Err(e) => return provider.problem(e),
+    };
+
+    let mut tokens = provider.tokens();
+    let request_token = req.token().unwrap();
+    let verified = tokens.pending.get(request_token)
+        .and_then(|x| x.verifier.as_deref())
+        .is_some_and(|x| Some(x) == req.parameter("oauth_verifier"));
+    if !verified {
+        drop(tokens);
+        let mut report = OAuthProblemReport::new(OAuthProblem::ParameterRejected);
+        report.parameters_rejected.push("oauth_verifier".to_owned());
+        return provider.problem(report);
+    }
+    // The request token can be used only once
+    tokens.pending.remove(request_token);
+
+    let token = random_string(24);
+    let secret = random_string(32);
+    tokens.access.insert(token.clone(), secret.clone());
+
+    if provider.config.twitter_quirks {
+        form_response(&[
+            ("oauth_token", &token), ("oauth_token_secret", &secret),
+            ("user_id", "1"), ("screen_name", "oauthcli_mock"),
+        ])
+    } else {
+        form_response(&[("oauth_token", &token), ("oauth_token_secret", &secret)])
+    }
+}
+
+async fn resource(State(provider): State<Arc<MockProvider>>, method: Method, uri: Uri, headers: HeaderMap, body: Bytes) -> Response {
+    match provider.authenticate(Step::Resource, &method, &uri, &headers, &body) {
+        Ok(req) => form_response(&[("oauth_token", req.token().unwrap())]),
+        Err(e) => provider.problem(e),
+    }
+}
@@ -0,0 +1,61 @@
+use std::env;
+use std::net::SocketAddr;
+use std::process;
+
+use oauthcli::OAuthProblem;
+use oauthcli_mock::{MockConfig, MockProvider};
+
+const USAGE: &str = "\
+Usage: oauthcli-mock [OPTIONS]
+
+Options:
+    --addr ADDR               Listen on ADDR (default: 127.0.0.1:8080)
+    --consumer-key KEY        The accepted consumer key (default: consumer)
+    --consumer-secret SECRET  The secret of the consumer (default: consumer_secret)
+    --twitter                 Emulate Twitter's quirks
+    --clock-offset SECONDS    Shift the clock of the provider to emulate clock skew
+    --timestamp-window SECS   Accepted difference of oauth_timestamp (default: 300)
+    --no-problem-reporting    Respond with an empty 401 instead of oauth_problem
+    --fail-with PROBLEM       Reject every signed request with oauth_problem=PROBLEM
+    --deny                    Deny every authorization";
+
+fn fail(message: &str) -> ! {
+    eprintln!("{}\n\n{}", message, USAGE);
+    process::exit(2)
+}
+
+#[tokio::main]
+async fn main() {
+    let mut addr: SocketAddr = "127.0.0.1:8080".parse().unwrap();
+    let mut config = MockConfig::default();
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().unwrap_or_else(|| fail(&format!("{} requires a value", name)));
+
+        match &arg[..] {
+            "--addr" => addr = value("--addr").parse().unwrap_or_else(|_| fail("Invalid --addr")),
+            "--consumer-key" => config.consumer_key = value("--consumer-key"),
+            "--consumer-secret" => config.consumer_secret = value("--consumer-secret"),
+            "--twitter" => config.twitter_quirks = true,
+            "--clock-offset" =>
+                config.clock_offset = value("--clock-offset").parse().unwrap_or_else(|_| fail("Invalid --clock-offset")),
+            "--timestamp-window" =>
+                config.timestamp_window = value("--timestamp-window").parse().unwrap_or_else(|_| fail("Invalid --timestamp-window")),
+            "--no-problem-reporting" => config.problem_reporting = false,
+            "--fail-with" => config.fail_with = Some(OAuthProblem::from(&value("--fail-with")[..])),
+            "--deny" => config.deny = true,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            x => fail(&format!("Unknown option: {}", x)),
+        }
+    }
+
+    println!("Listening on http://{}", addr);
+    if let Err(e) = MockProvider::new(config).serve(addr).await {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,137 @@
+use oauthcli::flow::RequestToken;
+use oauthcli::url::Url;
+use oauthcli::{
+    OAuthAuthorizationHeader, OAuthAuthorizationHeaderBuilder, OAuthCallback, OAuthProblem,
+    OAuthProblemReport, OAuthTokenResponse, SignatureMethod,
+};
+use oauthcli_mock::{MockConfig, MockProvider, MockServer};
+use reqwest::{header, redirect, StatusCode};
+
+async fn send(method: reqwest::Method, url: &Url, authorization: OAuthAuthorizationHeader) -> reqwest::Response {
+    reqwest::Client::new()
+        .request(method, url.as_str())
+        .header(header::AUTHORIZATION, authorization.to_string())
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn token_response(res: reqwest::Response) -> OAuthTokenResponse {
+    assert_eq!(res.status(), StatusCode::OK);
+    OAuthTokenResponse::from_body(&res.text().await.unwrap()).unwrap()
+}
+
+/// Runs the flow and returns the access token response.
+async fn sign_in(server: &MockServer, for_twitter: bool) -> OAuthTokenResponse {
+    let finish = |x: &OAuthAuthorizationHeaderBuilder| if for_twitter { x.finish_for_twitter() } else { x.finish() };
+
+    let url = server.url("/oauth/request_token");
+    let authorization = finish(&RequestToken::request(
+        &url, "consumer", "consumer_secret", SignatureMethod::HmacSha1, "http://client.example/callback"));
+    let request_token = RequestToken::from_response(&token_response(send(reqwest::Method::POST, &url, authorization).await).await).unwrap();
+
+    let mut url = server.url("/oauth/authorize");
+    url.query_pairs_mut().append_pair("oauth_token", request_token.token());
+    let client = reqwest::Client::builder().redirect(redirect::Policy::none()).build().unwrap();
+    let res = client.get(url.as_str()).send().await.unwrap();
+    let callback = Url::parse(res.headers()[header::LOCATION].to_str().unwrap()).unwrap();
+    let authorized = request_token.authorize(&OAuthCallback::from_url(&callback).unwrap()).unwrap();
+
+    let url = server.url("/oauth/access_token");
+    let authorization = finish(&authorized.access_token_request(&url, "consumer", "consumer_secret", SignatureMethod::HmacSha1));
+    token_response(send(reqwest::Method::POST, &url, authorization).await).await
+}
+
+#[tokio::test]
+async fn flow() {
+    let server = MockProvider::new(MockConfig::default()).start().await.unwrap();
+    let res = sign_in(&server, false).await;
+
+    let url = server.url("/resource?q=a%20b");
+    let mut builder = OAuthAuthorizationHeaderBuilder::new("GET", &url, "consumer", "consumer_secret", SignatureMethod::HmacSha1);
+    builder.token(&res.token[..], &res.token_secret).nonce("nonce");
+
+    // A forged request does not use the nonce
+    let mut forged = OAuthAuthorizationHeaderBuilder::new("GET", &url, "consumer", "consumer_secret", SignatureMethod::HmacSha1);
+    forged.token(&res.token[..], "wrong_secret").nonce("nonce");
+    assert_eq!(send(reqwest::Method::GET, &url, forged.finish()).await.status(), StatusCode::UNAUTHORIZED);
+
+    let authorization = builder.finish();
+    let resource = send(reqwest::Method::GET, &url, authorization.clone()).await;
+    assert_eq!(resource.status(), StatusCode::OK);
+    assert_eq!(resource.text().await.unwrap(), format!("oauth_token={}", res.token));
+
+    // The nonce is used
+    let res = send(reqwest::Method::GET, &url, authorization).await;
+    let report = OAuthProblemReport::from_www_authenticate(res.headers()[header::WWW_AUTHENTICATE].to_str().unwrap()).unwrap();
+    assert_eq!(report.problem, OAuthProblem::NonceUsed);
+}
+
+#[tokio::test]
+async fn twitter_quirks() {
+    let server = MockProvider::new(MockConfig { twitter_quirks: true, ..MockConfig::default() }).start().await.unwrap();
+    let res = sign_in(&server, true).await;
+    assert_eq!(res.parameter("screen_name"), Some("oauthcli_mock"));
+    let (res_token, res_token_secret) = (res.token.clone(), res.token_secret.clone());
+
+    // Twitter does not re-encode the query
+    let url = server.url("/resource?q=%7e");
+    let mut builder = OAuthAuthorizationHeaderBuilder::new("GET", &url, "consumer", "consumer_secret", SignatureMethod::HmacSha1);
+    builder.token(&res.token[..], &res.token_secret);
+    assert_eq!(send(reqwest::Method::GET, &url, builder.finish_for_twitter()).await.status(), StatusCode::OK);
+
+    let res = send(reqwest::Method::GET, &url, builder.finish()).await;
+    let report = OAuthProblemReport::from_body(&res.text().await.unwrap()).unwrap();
+    assert_eq!(report.problem, OAuthProblem::SignatureInvalid);
+
+    // The form body is not UTF-8
+    let url = server.url("/resource");
+    let body = &b"name=%83e%83X%83g"[..];
+    let content_type = "application/x-www-form-urlencoded";
+    let mut builder = OAuthAuthorizationHeaderBuilder::new("POST", &url, "consumer", "consumer_secret", SignatureMethod::HmacSha1);
+    builder.token(&res_token, &res_token_secret);
+    let authorization = builder.finish_with_body_for_twitter(Some(content_type), body).unwrap();
+    let res = reqwest::Client::new()
+        .post(url.as_str())
+        .header(header::AUTHORIZATION, authorization.to_string())
+        .header(header::CONTENT_TYPE, content_type)
+        .body(body)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn clock_skew() {
+    let server = MockProvider::new(MockConfig { clock_offset: 3600, ..MockConfig::default() }).start().await.unwrap();
+    let url = server.url("/oauth/request_token");
+    let authorization = RequestToken::request(&url, "consumer", "consumer_secret", SignatureMethod::HmacSha1, "oob").finish();
+    let res = send(reqwest::Method::POST, &url, authorization).await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+    let report = OAuthProblemReport::from_www_authenticate(res.headers()[header::WWW_AUTHENTICATE].to_str().unwrap()).unwrap();
+    assert_eq!(report.problem, OAuthProblem::TimestampRefused);
+    let (from, to) = report.acceptable_timestamps.unwrap();
+    assert_eq!(to - from, 600);
+}
+
+#[tokio::test]
+async fn problem_reports() {
+    let config = MockConfig { fail_with: Some(OAuthProblem::ConsumerKeyRefused), ..MockConfig::default() };
+    let server = MockProvider::new(config).start().await.unwrap();
+    let url = server.url("/oauth/request_token");
+    let authorization = RequestToken::request(&url, "consumer", "consumer_secret", SignatureMethod::HmacSha1, "oob").finish();
+    let res = send(reqwest::Method::POST, &url, authorization).await;
+    let report = OAuthProblemReport::from_body(&res.text().await.unwrap()).unwrap();
+    assert_eq!(report.problem, OAuthProblem::ConsumerKeyRefused);
+
+    let config = MockConfig { problem_reporting: false, ..MockConfig::default() };
+    let server = MockProvider::new(config).start().await.unwrap();
+    let url = server.url("/oauth/request_token");
+    let authorization = RequestToken::request(&url, "unknown", "consumer_secret", SignatureMethod::HmacSha1, "oob").finish();
+    let res = send(reqwest::Method::POST, &url, authorization).await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    assert!(res.headers().get(header::WWW_AUTHENTICATE).is_none());
+    assert_eq!(res.text().await.unwrap(), "");
+}
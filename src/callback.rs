@@ -0,0 +1,100 @@
+use std::error::Error;
+use std::fmt;
+use url::Url;
+use super::crypto;
+
+#[derive(Copy, Debug, PartialEq, Eq, Clone, Hash)]
+pub enum ParseOAuthCallbackError {
+    /// The user denied the authorization.
+    Denied,
+    /// The required parameter is not found.
+    ParameterAbsent(&'static str),
+    /// `oauth_token` is not the pending request token.
+    TokenMismatch
+}
+
+impl Error for ParseOAuthCallbackError {
+    fn description(&self) -> &str {
+        match *self {
+            ParseOAuthCallbackError::Denied => "The user denied the authorization",
+            ParseOAuthCallbackError::ParameterAbsent(_) => "The required parameter is not found",
+            ParseOAuthCallbackError::TokenMismatch => "`oauth_token` is not the pending request token"
+        }
+    }
+}
+
+impl fmt::Display for ParseOAuthCallbackError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseOAuthCallbackError::ParameterAbsent(x) => write!(f, "`{}` is not found", x),
+            _ => f.write_str(self.description())
+        }
+    }
+}
+
+/// Parameters which the Service Provider adds to the callback URI
+/// after the Resource Owner authorized the Client.
+///
+/// # Example
+/// ```
+/// # extern crate oauthcli;
+/// # extern crate url;
+/// # use oauthcli::*;
+/// # fn main() {
+/// let url = url::Url::parse(
+///     "http://printer.example.com/ready?oauth_token=hh5s93j4hdidpola&oauth_verifier=hfdp7dh39dks9884"
+/// ).unwrap();
+/// let callback = OAuthCallback::from_url(&url).unwrap();
+/// assert_eq!(callback.verifier_for("hh5s93j4hdidpola"), Ok("hfdp7dh39dks9884"));
+/// assert_eq!(callback.verifier_for("another_token"), Err(ParseOAuthCallbackError::TokenMismatch));
+///
+/// let url = url::Url::parse("http://printer.example.com/ready?denied=hh5s93j4hdidpola").unwrap();
+/// assert_eq!(OAuthCallback::from_url(&url).err(), Some(ParseOAuthCallbackError::Denied));
+/// # }
+/// ```
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct OAuthCallback {
+    token: String,
+    verifier: String
+}
+
+impl OAuthCallback {
+    /// Parses the query of the callback URI.
+    ///
+    /// Returns `Err(Denied)` if the query has `denied` (Twitter)
+    /// or `oauth_problem=user_refused` (OAuth Problem Reporting).
+    pub fn from_url(url: &Url) -> Result<OAuthCallback, ParseOAuthCallbackError> {
+        let mut token = None;
+        let mut verifier = None;
+
+        for (k, v) in url.query_pairs() {
+            match &k[..] {
+                "oauth_token" => token = Some(v.into_owned()),
+                "oauth_verifier" => verifier = Some(v.into_owned()),
+                "denied" => return Err(ParseOAuthCallbackError::Denied),
+                "oauth_problem" if v == "user_refused" => return Err(ParseOAuthCallbackError::Denied),
+                _ => ()
+            }
+        }
+
+        Ok(OAuthCallback {
+            token: try!(token.ok_or(ParseOAuthCallbackError::ParameterAbsent("oauth_token"))),
+            verifier: try!(verifier.ok_or(ParseOAuthCallbackError::ParameterAbsent("oauth_verifier")))
+        })
+    }
+
+    /// `oauth_token`, which should be the request token.
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    /// Returns `oauth_verifier` to be passed to `OAuthAuthorizationHeaderBuilder::verifier`
+    /// if `oauth_token` is `request_token`.
+    pub fn verifier_for(&self, request_token: &str) -> Result<&str, ParseOAuthCallbackError> {
+        if crypto::constant_time_eq(self.token.as_bytes(), request_token.as_bytes()) {
+            Ok(&self.verifier)
+        } else {
+            Err(ParseOAuthCallbackError::TokenMismatch)
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use std::borrow::{Borrow, Cow};
+use std::fmt;
+use super::{OAuthProblem, OAuthProblemReport, ParseOAuthProblemError};
+use super::{format_auth_params, parse_auth_params, remove_oauth_scheme};
+
+/// `WWW-Authenticate` header for OAuth.
+///
+/// # Example
+/// ```
+/// # use oauthcli::{OAuthChallenge, OAuthChallengeBuilder, OAuthProblem};
+/// let challenge = OAuthChallengeBuilder::new()
+///     .realm("Photos")
+///     .problem(OAuthProblem::TokenExpired)
+///     .finish();
+/// assert_eq!(challenge.to_string(), "OAuth realm=\"Photos\",oauth_problem=\"token_expired\"");
+///
+/// let parsed: OAuthChallenge = challenge.to_string().parse().unwrap();
+/// assert_eq!(parsed, challenge);
+/// ```
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct OAuthChallenge {
+    realm: Option<String>,
+    problem_report: Option<OAuthProblemReport>
+}
+
+impl OAuthChallenge {
+    pub fn realm(&self) -> Option<&str> {
+        self.realm.as_ref().map(|x| &x[..])
+    }
+
+    /// The problem reported with `oauth_problem` parameter.
+    pub fn problem_report(&self) -> Option<&OAuthProblemReport> {
+        self.problem_report.as_ref()
+    }
+
+    pub fn problem_report_owned(self) -> Option<OAuthProblemReport> {
+        self.problem_report
+    }
+
+    /// `auth-param` in RFC 7235
+    pub fn auth_param(&self) -> String {
+        let problem_params = self.problem_report.as_ref()
+            .map(|x| x.to_parameters())
+            .unwrap_or_else(Vec::new);
+
+        format_auth_params(
+            self.realm.as_ref()
+                .map(|x| ("realm", &x[..]))
+                .into_iter()
+                .chain(problem_params.iter().map(|&(k, ref v)| (k, &v[..])))
+        )
+    }
+}
+
+impl fmt::Display for OAuthChallenge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let auth_param = self.auth_param();
+
+        if auth_param.len() > 0 {
+            try!(f.write_str("OAuth "));
+            f.write_str(&auth_param)
+        } else {
+            f.write_str("OAuth")
+        }
+    }
+}
+
+impl ::std::str::FromStr for OAuthChallenge {
+    type Err = ParseOAuthProblemError;
+
+    fn from_str(s: &str) -> Result<OAuthChallenge, ParseOAuthProblemError> {
+        let mut realm = None;
+        let mut params = Vec::new();
+
+        // The parameters other than OAuth's, such as realm, are plain quoted-strings
+        for (k, v) in try!(parse_auth_params(remove_oauth_scheme(s), |x| !x.starts_with("oauth_"))) {
+            if k == "realm" {
+                realm = Some(v);
+            } else {
+                params.push((Cow::Owned(k), Cow::Owned(v)));
+            }
+        }
+
+        let problem_report = match OAuthProblemReport::from_parameters(params) {
+            Ok(x) => Some(x),
+            Err(ParseOAuthProblemError::ProblemAbsent) => None,
+            Err(e) => return Err(e)
+        };
+
+        Ok(OAuthChallenge { realm: realm, problem_report: problem_report })
+    }
+}
+
+pub struct OAuthChallengeBuilder<'a> {
+    realm: Option<Cow<'a, str>>,
+    problem_report: Option<OAuthProblemReport>
+}
+
+impl<'a> OAuthChallengeBuilder<'a> {
+    pub fn new() -> Self {
+        OAuthChallengeBuilder {
+            realm: None,
+            problem_report: None
+        }
+    }
+
+    pub fn realm<T: Into<Cow<'a, str>>>(&mut self, realm: T) -> &mut Self {
+        self.realm = Some(realm.into());
+        self
+    }
+
+    /// Sets `oauth_problem` without any additional parameters.
+    pub fn problem(&mut self, problem: OAuthProblem) -> &mut Self {
+        self.problem_report = Some(OAuthProblemReport::new(problem));
+        self
+    }
+
+    /// Sets `oauth_problem` and the additional parameters such as `oauth_acceptable_timestamps`.
+    pub fn problem_report(&mut self, problem_report: OAuthProblemReport) -> &mut Self {
+        self.problem_report = Some(problem_report);
+        self
+    }
+
+    /// Generate `WWW-Authenticate` header for OAuth.
+    pub fn finish(&self) -> OAuthChallenge {
+        OAuthChallenge {
+            realm: self.realm.as_ref().map(|x| (x.borrow() as &str).to_owned()),
+            problem_report: self.problem_report.clone()
+        }
+    }
+}
@@ -0,0 +1,35 @@
+//! Credentials of [RFC 5849 section 1.1](https://tools.ietf.org/html/rfc5849#section-1.1).
+
+use alloc::string::String;
+use super::{ConsumerSecret, TokenSecret};
+
+/// Client credentials, which are called consumer key and consumer secret in OAuth 1.0a.
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ClientCredentials {
+    /// `oauth_consumer_key`
+    pub key: String,
+    pub secret: ConsumerSecret<'static>
+}
+
+impl ClientCredentials {
+    pub fn new<K: Into<String>, S: Into<String>>(key: K, secret: S) -> ClientCredentials {
+        ClientCredentials { key: key.into(), secret: ConsumerSecret::new(secret.into()) }
+    }
+}
+
+/// Temporary credentials or token credentials.
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TokenCredentials {
+    /// `oauth_token`
+    pub token: String,
+    /// `oauth_token_secret`
+    pub secret: TokenSecret<'static>
+}
+
+impl TokenCredentials {
+    pub fn new<T: Into<String>, S: Into<String>>(token: T, secret: S) -> TokenCredentials {
+        TokenCredentials { token: token.into(), secret: TokenSecret::new(secret.into()) }
+    }
+}
@@ -0,0 +1,244 @@
+//! Cryptographic primitives provided by the backend selected with cargo features.
+//!
+//! | Feature | Backend |
+//! |---------|---------|
+//! | `ring` (default) | [ring](https://github.com/briansmith/ring) |
+//! | `rustcrypto` | [RustCrypto](https://github.com/RustCrypto) (`hmac`, `sha1`, `sha2` and `rsa`) |
+//! | `openssl` | [OpenSSL](https://github.com/sfackler/rust-openssl) |
+//!
+//! If more than one backend is enabled, `openssl` is preferred to `rustcrypto`,
+//! and `rustcrypto` is preferred to `ring`.
+//!
+//! RSA keys are DER-encoded PKCS #1 `RSAPrivateKey` and `RSAPublicKey`.
+//! The `ring` backend verifies RSA-SHA1 signatures but cannot sign with SHA-1.
+
+use alloc::fmt;
+use alloc::vec::Vec;
+#[cfg(feature = "std")] use std::error::Error;
+
+#[cfg(not(any(feature = "ring", feature = "rustcrypto", feature = "openssl")))]
+compile_error!("Enable one of the crypto backend features: `ring`, `rustcrypto` or `openssl`");
+
+#[derive(Copy, Debug, PartialEq, Eq, Clone, Hash)]
+pub enum CryptoError {
+    /// The operation is not supported by the backend.
+    Unsupported,
+    /// The key is malformed.
+    InvalidKey
+}
+
+impl CryptoError {
+    fn message(&self) -> &'static str {
+        match *self {
+            CryptoError::Unsupported => "The operation is not supported by the crypto backend",
+            CryptoError::InvalidKey => "The key is malformed"
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Error for CryptoError {
+    fn description(&self) -> &str {
+        self.message()
+    }
+}
+
+impl fmt::Display for CryptoError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+/// The name of the selected backend: `"ring"`, `"rustcrypto"` or `"openssl"`.
+pub const BACKEND: &'static str = imp::NAME;
+
+/// HMAC-SHA1 of `msg` with `key`.
+pub fn hmac_sha1(key: &[u8], msg: &[u8]) -> Vec<u8> {
+    imp::hmac_sha1(key, msg)
+}
+
+/// HMAC-SHA256 of `msg` with `key`.
+pub fn hmac_sha256(key: &[u8], msg: &[u8]) -> Vec<u8> {
+    imp::hmac_sha256(key, msg)
+}
+
+/// SHA-1 digest of `data`.
+pub fn sha1(data: &[u8]) -> Vec<u8> {
+    imp::sha1(data)
+}
+
+/// SHA-256 digest of `data`.
+pub fn sha256(data: &[u8]) -> Vec<u8> {
+    imp::sha256(data)
+}
+
+/// Signs `msg` with RSASSA-PKCS1-v1_5 and SHA-1.
+pub fn rsa_sha1_sign(private_key: &[u8], msg: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    imp::rsa_sha1_sign(private_key, msg)
+}
+
+/// Verifies `signature` of `msg` made by `rsa_sha1_sign`.
+pub fn rsa_sha1_verify(public_key: &[u8], msg: &[u8], signature: &[u8]) -> Result<bool, CryptoError> {
+    imp::rsa_sha1_verify(public_key, msg, signature)
+}
+
+/// Compares `a` and `b` in constant time.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    imp::constant_time_eq(a, b)
+}
+
+// ring deprecated its constant_time module
+#[cfg(not(feature = "openssl"))]
+fn xor_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(all(feature = "ring", not(any(feature = "rustcrypto", feature = "openssl"))))]
+mod imp {
+    use alloc::vec::Vec;
+    use ring::{digest, hmac, signature};
+    use super::CryptoError;
+
+    pub const NAME: &'static str = "ring";
+
+    fn hmac(algorithm: hmac::Algorithm, key: &[u8], msg: &[u8]) -> Vec<u8> {
+        hmac::sign(&hmac::Key::new(algorithm, key), msg).as_ref().to_vec()
+    }
+
+    pub fn hmac_sha1(key: &[u8], msg: &[u8]) -> Vec<u8> {
+        hmac(hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY, key, msg)
+    }
+
+    pub fn hmac_sha256(key: &[u8], msg: &[u8]) -> Vec<u8> {
+        hmac(hmac::HMAC_SHA256, key, msg)
+    }
+
+    pub fn sha1(data: &[u8]) -> Vec<u8> {
+        digest::digest(&digest::SHA1_FOR_LEGACY_USE_ONLY, data).as_ref().to_vec()
+    }
+
+    pub fn sha256(data: &[u8]) -> Vec<u8> {
+        digest::digest(&digest::SHA256, data).as_ref().to_vec()
+    }
+
+    pub fn rsa_sha1_sign(_: &[u8], _: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        // ring does not sign with SHA-1
+        Err(CryptoError::Unsupported)
+    }
+
+    pub fn rsa_sha1_verify(public_key: &[u8], msg: &[u8], signature: &[u8]) -> Result<bool, CryptoError> {
+        // ring does not tell a malformed key from a wrong signature
+        let key = signature::UnparsedPublicKey::new(&signature::RSA_PKCS1_1024_8192_SHA1_FOR_LEGACY_USE_ONLY, public_key);
+        Ok(key.verify(msg, signature).is_ok())
+    }
+
+    pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+        super::xor_eq(a, b)
+    }
+}
+
+#[cfg(all(feature = "rustcrypto", not(feature = "openssl")))]
+mod imp {
+    use alloc::vec::Vec;
+    use hmac::{Hmac, Mac};
+    use rsa::{Pkcs1v15Sign, RsaPrivateKey, RsaPublicKey};
+    use rsa::pkcs1::{DecodeRsaPrivateKey, DecodeRsaPublicKey};
+    use sha1::{Digest, Sha1};
+    use sha2::Sha256;
+    use super::CryptoError;
+
+    pub const NAME: &'static str = "rustcrypto";
+
+    pub fn hmac_sha1(key: &[u8], msg: &[u8]) -> Vec<u8> {
+        let mut mac = Hmac::<Sha1>::new_from_slice(key).expect("HMAC accepts a key of any length");
+        mac.update(msg);
+        mac.finalize().into_bytes().to_vec()
+    }
+
+    pub fn hmac_sha256(key: &[u8], msg: &[u8]) -> Vec<u8> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts a key of any length");
+        mac.update(msg);
+        mac.finalize().into_bytes().to_vec()
+    }
+
+    pub fn sha1(data: &[u8]) -> Vec<u8> {
+        Sha1::digest(data).to_vec()
+    }
+
+    pub fn sha256(data: &[u8]) -> Vec<u8> {
+        Sha256::digest(data).to_vec()
+    }
+
+    pub fn rsa_sha1_sign(private_key: &[u8], msg: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        let key = try!(RsaPrivateKey::from_pkcs1_der(private_key).map_err(|_| CryptoError::InvalidKey));
+        key.sign(Pkcs1v15Sign::new::<Sha1>(), &Sha1::digest(msg))
+            .map_err(|_| CryptoError::InvalidKey)
+    }
+
+    pub fn rsa_sha1_verify(public_key: &[u8], msg: &[u8], signature: &[u8]) -> Result<bool, CryptoError> {
+        let key = try!(RsaPublicKey::from_pkcs1_der(public_key).map_err(|_| CryptoError::InvalidKey));
+        Ok(key.verify(Pkcs1v15Sign::new::<Sha1>(), &Sha1::digest(msg), signature).is_ok())
+    }
+
+    pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+        super::xor_eq(a, b)
+    }
+}
+
+#[cfg(feature = "openssl")]
+mod imp {
+    use alloc::vec::Vec;
+    use openssl::hash::{MessageDigest, hash};
+    use openssl::memcmp;
+    use openssl::pkey::PKey;
+    use openssl::rsa::Rsa;
+    use openssl::sign::{Signer, Verifier};
+    use super::CryptoError;
+
+    pub const NAME: &'static str = "openssl";
+
+    fn hmac(digest: MessageDigest, key: &[u8], msg: &[u8]) -> Vec<u8> {
+        let key = PKey::hmac(key).expect("Failed to create HMAC key");
+        let mut signer = Signer::new(digest, &key).expect("Failed to create HMAC signer");
+        signer.update(msg).expect("Failed to update HMAC");
+        signer.sign_to_vec().expect("Failed to finalize HMAC")
+    }
+
+    pub fn hmac_sha1(key: &[u8], msg: &[u8]) -> Vec<u8> {
+        hmac(MessageDigest::sha1(), key, msg)
+    }
+
+    pub fn hmac_sha256(key: &[u8], msg: &[u8]) -> Vec<u8> {
+        hmac(MessageDigest::sha256(), key, msg)
+    }
+
+    pub fn sha1(data: &[u8]) -> Vec<u8> {
+        hash(MessageDigest::sha1(), data).expect("Failed to calculate SHA-1").to_vec()
+    }
+
+    pub fn sha256(data: &[u8]) -> Vec<u8> {
+        hash(MessageDigest::sha256(), data).expect("Failed to calculate SHA-256").to_vec()
+    }
+
+    pub fn rsa_sha1_sign(private_key: &[u8], msg: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        let key = try!(Rsa::private_key_from_der(private_key)
+            .and_then(PKey::from_rsa)
+            .map_err(|_| CryptoError::InvalidKey));
+        Signer::new(MessageDigest::sha1(), &key)
+            .and_then(|mut signer| { try!(signer.update(msg)); signer.sign_to_vec() })
+            .map_err(|_| CryptoError::InvalidKey)
+    }
+
+    pub fn rsa_sha1_verify(public_key: &[u8], msg: &[u8], signature: &[u8]) -> Result<bool, CryptoError> {
+        let key = try!(Rsa::public_key_from_der_pkcs1(public_key)
+            .and_then(PKey::from_rsa)
+            .map_err(|_| CryptoError::InvalidKey));
+        Ok(Verifier::new(MessageDigest::sha1(), &key)
+            .and_then(|mut verifier| { try!(verifier.update(msg)); verifier.verify(signature) })
+            .unwrap_or(false))
+    }
+
+    pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+        a.len() == b.len() && memcmp::eq(a, b)
+    }
+}
@@ -0,0 +1,171 @@
+//! Diagnosis of signatures which do not match the ones calculated by the Service Provider.
+//!
+//! # Example
+//! ```
+//! # extern crate oauthcli;
+//! # extern crate url;
+//! # use oauthcli::*;
+//! # use oauthcli::diagnosis::Variation;
+//! # fn main() {
+//! let url = url::Url::parse("http://example.com/resource?q=a%20b").unwrap();
+//! let mut builder =
+//!     OAuthAuthorizationHeaderBuilder::new(
+//!         "GET", &url, "consumer", "secret", SignatureMethod::HmacSha1);
+//! builder.nonce("nonce").timestamp(0);
+//!
+//! // The signature which the Service Provider expects
+//! let expected = "C/SjY/676VFfvzvQIxORg4ZX4Ws=";
+//! assert_eq!(builder.diagnose(expected), Ok(Some(vec![Variation::SpaceAsPlus])));
+//! # }
+//! ```
+
+use std::borrow::{Borrow, Cow};
+use std::error::Error;
+use std::fmt;
+use url::Url;
+use super::{OAuthAuthorizationHeaderBuilder, signature_parameters};
+use super::signing::{self, percent_encode};
+
+/// A known way to calculate the signature differently from RFC 5849.
+#[derive(Copy, Debug, PartialEq, Eq, Clone, Hash)]
+pub enum Variation {
+    /// The query is not re-encoded like `finish_for_twitter`.
+    TwitterQuery,
+    /// `oauth_version` is excluded if the builder includes it, or included otherwise.
+    ToggleVersion,
+    /// Spaces are encoded as `+` instead of `%20`.
+    SpaceAsPlus,
+    /// The default port such as `:443` remains in the base string URI.
+    DefaultPort,
+    /// Parameters with the same name are kept in the original order instead of sorted by their values.
+    UnsortedDuplicates,
+    /// The trailing slash of the path is removed if it exists, or added otherwise.
+    ToggleTrailingSlash
+}
+
+#[derive(Copy, Debug, PartialEq, Eq, Clone, Hash)]
+pub enum DiagnoseError {
+    /// The timestamp of the builder is not set.
+    TimestampAbsent,
+    /// The nonce of the builder is not set.
+    NonceAbsent,
+    /// The URL does not have a host.
+    InvalidUrl
+}
+
+impl Error for DiagnoseError {
+    fn description(&self) -> &str {
+        match *self {
+            DiagnoseError::TimestampAbsent => "The timestamp is not set",
+            DiagnoseError::NonceAbsent => "The nonce is not set",
+            DiagnoseError::InvalidUrl => "The URL does not have a host"
+        }
+    }
+}
+
+impl fmt::Display for DiagnoseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.description())
+    }
+}
+
+/// All variations in the order of `diagnose` trying them.
+pub const VARIATIONS: &'static [Variation] = &[
+    Variation::TwitterQuery,
+    Variation::ToggleVersion,
+    Variation::SpaceAsPlus,
+    Variation::DefaultPort,
+    Variation::UnsortedDuplicates,
+    Variation::ToggleTrailingSlash
+];
+
+impl<'a> OAuthAuthorizationHeaderBuilder<'a> {
+    /// Finds the variations which reproduce `expected_signature`,
+    /// the decoded `oauth_signature` calculated for the same inputs.
+    ///
+    /// Returns the fewest variations, an empty `Vec` if `finish` already reproduces it,
+    /// or `None` if no combination of `VARIATIONS` does.
+    /// Request bodies are not supported, so pass the form-encoded parameters to `request_parameters`.
+    ///
+    /// Returns `Err` if the timestamp or the nonce is not set, or `url` does not have a host.
+    pub fn diagnose(&self, expected_signature: &str) -> Result<Option<Vec<Variation>>, DiagnoseError> {
+        let timestamp = try!(self.timestamp.ok_or(DiagnoseError::TimestampAbsent)).to_string();
+        let nonce = try!(self.nonce.as_ref().ok_or(DiagnoseError::NonceAbsent));
+        let host = try!(self.url.host_str().ok_or(DiagnoseError::InvalidUrl));
+        let oauth_params = self.oauth_parameters(&timestamp, nonce.borrow(), None);
+
+        let mut combinations: Vec<Vec<Variation>> = (0..1 << VARIATIONS.len())
+            .map(|bits: usize| VARIATIONS.iter().enumerate()
+                .filter(|&(i, _)| bits & (1 << i) != 0)
+                .map(|(_, &x)| x)
+                .collect())
+            .collect();
+        combinations.sort_by_key(|x| x.len());
+
+        Ok(combinations.into_iter().find(|variations| {
+            let base_string = self.variant_base_string(&oauth_params, host, variations);
+            self.signer.sign(&base_string, self.consumer_secret.expose(), self.token_secret.as_ref().map(|x| x.expose()))
+                .map(|x| x == expected_signature)
+                .unwrap_or(false)
+        }))
+    }
+
+    fn variant_base_string(&self, oauth_params: &[(&str, &str)], host: &str, variations: &[Variation]) -> String {
+        let has = |x| variations.contains(&x);
+
+        let mut oauth_params = oauth_params.to_vec();
+        if has(Variation::ToggleVersion) {
+            if self.include_version { oauth_params.retain(|&(k, _)| k != "oauth_version"); }
+            else { oauth_params.push(("oauth_version", "1.0")); }
+        }
+
+        let url: Cow<Url> =
+            if has(Variation::ToggleTrailingSlash) { Cow::Owned(toggle_trailing_slash(self.url)) }
+            else { Cow::Borrowed(self.url) };
+
+        let params = oauth_params.iter()
+            .map(|&(k, v)| (k.as_bytes().into(), v.as_bytes().into()))
+            .chain(self.parameters.iter()
+                .map(|&(ref k, ref v)| (Cow::Borrowed(k.borrow()), Cow::Borrowed(v.borrow()))));
+        let mut params = signature_parameters(&url, params, has(Variation::TwitterQuery)).0;
+
+        if has(Variation::SpaceAsPlus) {
+            for &mut (ref mut k, ref mut v) in params.iter_mut() {
+                *k = k.replace("%20", "+").into();
+                *v = v.replace("%20", "+").into();
+            }
+        }
+
+        if has(Variation::UnsortedDuplicates) { params.sort_by(|x, y| x.0.cmp(&y.0)); }
+        else { params.sort(); }
+
+        let normalized = params.iter()
+            .map(|&(ref k, ref v)| format!("{}={}", k, v))
+            .collect::<Vec<_>>()
+            .join("&");
+
+        let uri = match url.port_or_known_default() {
+            Some(port) if has(Variation::DefaultPort) && url.port().is_none() =>
+                format!("{}://{}:{}{}", url.scheme().to_lowercase(), host.to_lowercase(), port, url.path()),
+            port => signing::base_string_uri(url.scheme(), host, port, url.path())
+        };
+
+        let mut base_string = self.method.to_ascii_uppercase();
+        base_string.push('&');
+        base_string.extend(percent_encode(uri.as_bytes()));
+        base_string.push('&');
+        base_string.extend(percent_encode(normalized.as_bytes()));
+        base_string
+    }
+}
+
+fn toggle_trailing_slash(url: &Url) -> Url {
+    let mut url = url.clone();
+    let path = match url.path() {
+        "/" => return url,
+        x if x.ends_with('/') => x[..x.len() - 1].to_owned(),
+        x => format!("{}/", x)
+    };
+    url.set_path(&path);
+    url
+}
@@ -0,0 +1,158 @@
+//! [OAuth Echo](https://developer.twitter.com/en/docs/basics/authentication/overview/oauth-echo)
+//!
+//! The client signs a request to the verify-credentials URL of the Service Provider,
+//! and delegates it to the third-party service with two headers.
+//! The service validates the headers by replaying the request.
+
+use std::error::Error;
+use std::fmt;
+use url::Url;
+use super::{OAuthAuthorizationHeader, OAuthAuthorizationHeaderBuilder, ParseOAuthAuthorizationHeaderError};
+
+pub const SERVICE_PROVIDER_HEADER: &'static str = "X-Auth-Service-Provider";
+pub const VERIFY_CREDENTIALS_AUTHORIZATION_HEADER: &'static str = "X-Verify-Credentials-Authorization";
+
+/// Headers to be sent to the delegate.
+#[derive(Debug, Clone)]
+pub struct OAuthEchoHeaders {
+    /// The value of `X-Auth-Service-Provider` header.
+    pub service_provider: String,
+    /// The value of `X-Verify-Credentials-Authorization` header.
+    pub verify_credentials_authorization: OAuthAuthorizationHeader
+}
+
+/// Generates OAuth Echo headers.
+///
+/// `builder` should be created for GET the verify-credentials URL with the user's token.
+///
+/// # Panics
+/// This function will panic if `url` is not valid for HTTP or HTTPS.
+///
+/// # Example
+/// ```
+/// # extern crate oauthcli;
+/// # extern crate url;
+/// # use oauthcli::*;
+/// # fn main() {
+/// let url = url::Url::parse("https://api.twitter.com/1.1/account/verify_credentials.json").unwrap();
+/// let headers = echo::headers(
+///     OAuthAuthorizationHeaderBuilder::new("GET", &url, "consumer", "secret", SignatureMethod::HmacSha1)
+///         .token("token", "secret"));
+/// assert_eq!(headers.service_provider, "https://api.twitter.com/1.1/account/verify_credentials.json");
+/// # }
+/// ```
+pub fn headers(builder: &OAuthAuthorizationHeaderBuilder) -> OAuthEchoHeaders {
+    OAuthEchoHeaders {
+        service_provider: builder.url.as_str().to_owned(),
+        verify_credentials_authorization: builder.finish()
+    }
+}
+
+/// Generates OAuth Echo headers with `finish_for_twitter`.
+///
+/// # Panics
+/// This function will panic if `url` is not valid for HTTP or HTTPS.
+pub fn headers_for_twitter(builder: &OAuthAuthorizationHeaderBuilder) -> OAuthEchoHeaders {
+    OAuthEchoHeaders {
+        service_provider: builder.url.as_str().to_owned(),
+        verify_credentials_authorization: builder.finish_for_twitter()
+    }
+}
+
+/// Response from the verify-credentials URL.
+#[derive(Debug, Clone)]
+pub struct EchoResponse {
+    pub status: u16,
+    pub body: Vec<u8>
+}
+
+/// HTTP client to replay the echoed request.
+pub trait EchoTransport {
+    type Error;
+
+    /// Sends GET request to `url` with `Authorization: {authorization}` header.
+    fn get(&self, url: &Url, authorization: &str) -> Result<EchoResponse, Self::Error>;
+}
+
+#[derive(Debug)]
+pub enum EchoError<E> {
+    /// `X-Auth-Service-Provider` is not a verify-credentials URL allowed by `EchoVerifier`.
+    ServiceProviderRejected,
+    /// `X-Verify-Credentials-Authorization` is not valid.
+    InvalidAuthorization(ParseOAuthAuthorizationHeaderError),
+    /// The transport failed to send the request.
+    Transport(E),
+    /// The Service Provider responded with a non-2xx status.
+    Rejected(EchoResponse)
+}
+
+impl<E: Error> Error for EchoError<E> {
+    fn description(&self) -> &str {
+        match *self {
+            EchoError::ServiceProviderRejected => "The service provider is not allowed",
+            EchoError::InvalidAuthorization(ref e) => e.description(),
+            EchoError::Transport(ref e) => e.description(),
+            EchoError::Rejected(_) => "The service provider rejected the credentials"
+        }
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            EchoError::InvalidAuthorization(ref e) => Some(e),
+            EchoError::Transport(ref e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+impl<E: Error> fmt::Display for EchoError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EchoError::Rejected(ref res) => write!(f, "The service provider rejected the credentials with status {}", res.status),
+            _ => f.write_str(self.description())
+        }
+    }
+}
+
+/// Validates OAuth Echo headers by sending them to the allowed verify-credentials URLs.
+///
+/// `X-Auth-Service-Provider` is given by the client, so it must be checked
+/// not to send the credentials to an arbitrary host.
+#[derive(Debug, Clone)]
+pub struct EchoVerifier {
+    service_providers: Vec<Url>
+}
+
+impl EchoVerifier {
+    pub fn new() -> EchoVerifier {
+        EchoVerifier { service_providers: Vec::new() }
+    }
+
+    /// Allows `url` as `X-Auth-Service-Provider`.
+    pub fn allow_service_provider(&mut self, url: Url) -> &mut Self {
+        self.service_providers.push(url);
+        self
+    }
+
+    /// Replays the echoed request and returns the response from the Service Provider,
+    /// which usually describes the user.
+    pub fn verify<T: EchoTransport>(&self, transport: &T, service_provider: &str, verify_credentials_authorization: &str)
+        -> Result<EchoResponse, EchoError<T::Error>>
+    {
+        let url = match Url::parse(service_provider) {
+            Ok(ref x) if self.service_providers.contains(x) => x.clone(),
+            _ => return Err(EchoError::ServiceProviderRejected)
+        };
+
+        let authorization: OAuthAuthorizationHeader = try!(
+            verify_credentials_authorization.parse().map_err(EchoError::InvalidAuthorization));
+
+        let res = try!(transport.get(&url, &authorization.to_string()).map_err(EchoError::Transport));
+
+        if res.status >= 200 && res.status < 300 {
+            Ok(res)
+        } else {
+            Err(EchoError::Rejected(res))
+        }
+    }
+}
@@ -0,0 +1,211 @@
+//! Type-state API for the authorization flow of
+//! [RFC 5849 section 2](https://tools.ietf.org/html/rfc5849#section-2).
+//!
+//! `RequestToken` → `AuthorizedRequestToken` → `AccessToken`.
+//! Each step creates the builder with the token and the verifier of that step.
+//! The types are `Serialize` but not `Deserialize`, so that a step cannot be skipped.
+//!
+//! # Example
+//! ```
+//! # extern crate oauthcli;
+//! # extern crate url;
+//! # use oauthcli::*;
+//! # use oauthcli::flow::*;
+//! # fn main() {
+//! let request_token_url = url::Url::parse("https://photos.example.net/initiate").unwrap();
+//! let header = RequestToken::request(
+//!     &request_token_url, "dpf43f3p2l4k3l03", "kd94hf93k423kf44", SignatureMethod::HmacSha1,
+//!     "http://printer.example.com/ready").finish();
+//!
+//! let res = OAuthTokenResponse::from_body(
+//!     "oauth_token=hh5s93j4hdidpola&oauth_token_secret=hdhd0244k9j7ao03&oauth_callback_confirmed=true"
+//! ).unwrap();
+//! let request_token = RequestToken::from_response(&res).unwrap();
+//!
+//! // Redirect the user to the authorization URI, and then receive the callback
+//! let callback = url::Url::parse(
+//!     "http://printer.example.com/ready?oauth_token=hh5s93j4hdidpola&oauth_verifier=hfdp7dh39dks9884"
+//! ).unwrap();
+//! let authorized = request_token.authorize(&OAuthCallback::from_url(&callback).unwrap()).unwrap();
+//!
+//! let access_token_url = url::Url::parse("https://photos.example.net/token").unwrap();
+//! let header = authorized.access_token_request(
+//!     &access_token_url, "dpf43f3p2l4k3l03", "kd94hf93k423kf44", SignatureMethod::HmacSha1).finish();
+//!
+//! let res = OAuthTokenResponse::from_body("oauth_token=nnch734d00sl2jdk&oauth_token_secret=pfkkdhi9sl3r4s00").unwrap();
+//! let access_token = authorized.into_access_token(&res);
+//! assert_eq!(access_token.token(), "nnch734d00sl2jdk");
+//! # }
+//! ```
+
+use std::borrow::Cow;
+use std::error::Error;
+use std::fmt;
+use url::Url;
+use super::{ConsumerSecret, OAuthAuthorizationHeaderBuilder, OAuthCallback, OAuthTokenResponse};
+use super::{ParseOAuthCallbackError, SignatureMethod, TokenCredentials, TokenSecret};
+
+#[derive(Copy, Debug, PartialEq, Eq, Clone, Hash)]
+pub enum FlowError {
+    /// `oauth_callback_confirmed` is not `true`, so the Service Provider does not support OAuth 1.0a.
+    CallbackNotConfirmed,
+    /// The callback is not valid for the request token.
+    CallbackError(ParseOAuthCallbackError)
+}
+
+impl Error for FlowError {
+    fn description(&self) -> &str {
+        match *self {
+            FlowError::CallbackNotConfirmed => "`oauth_callback_confirmed` is not true",
+            FlowError::CallbackError(ref e) => e.description()
+        }
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            FlowError::CallbackError(ref e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+impl fmt::Display for FlowError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FlowError::CallbackError(ref e) => fmt::Display::fmt(e, f),
+            _ => f.write_str(self.description())
+        }
+    }
+}
+
+impl From<ParseOAuthCallbackError> for FlowError {
+    fn from(e: ParseOAuthCallbackError) -> FlowError {
+        FlowError::CallbackError(e)
+    }
+}
+
+/// Temporary credentials which wait for the authorization by the Resource Owner.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct RequestToken {
+    token: String,
+    secret: TokenSecret<'static>
+}
+
+impl RequestToken {
+    /// Creates a builder for POST Temporary Credential Request with `oauth_callback`.
+    /// Pass `"oob"` as `callback` if the verifier is entered by the user.
+    pub fn request<'a, C, S, B>(url: &'a Url, consumer_key: C, consumer_secret: S, signature_method: SignatureMethod, callback: B)
+        -> OAuthAuthorizationHeaderBuilder<'a>
+        where C: Into<Cow<'a, str>>, S: Into<ConsumerSecret<'a>>, B: Into<Cow<'a, str>>
+    {
+        let mut builder = OAuthAuthorizationHeaderBuilder::new("POST", url, consumer_key, consumer_secret, signature_method);
+        builder.callback(callback);
+        builder
+    }
+
+    /// Restores the request token which was accepted by `from_response` and saved in a `PendingTokenStore`.
+    ///
+    /// This skips the check of `oauth_callback_confirmed`, so it is not a part of the public API.
+    #[doc(hidden)]
+    pub fn from_confirmed_parts<T: Into<String>, S: Into<TokenSecret<'static>>>(token: T, secret: S) -> RequestToken {
+        RequestToken { token: token.into(), secret: secret.into() }
+    }
+
+    /// Accepts the response only if `oauth_callback_confirmed` is `true`.
+    pub fn from_response(res: &OAuthTokenResponse) -> Result<RequestToken, FlowError> {
+        if res.callback_confirmed != Some(true) {
+            return Err(FlowError::CallbackNotConfirmed);
+        }
+
+        Ok(RequestToken { token: res.token.clone(), secret: res.token_secret.clone() })
+    }
+
+    /// `oauth_token` to be sent to the Resource Owner Authorization endpoint.
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    pub fn secret(&self) -> &TokenSecret<'static> {
+        &self.secret
+    }
+
+    /// Accepts `oauth_verifier` of the callback if `oauth_token` of the callback is this token.
+    pub fn authorize(self, callback: &OAuthCallback) -> Result<AuthorizedRequestToken, FlowError> {
+        let verifier = try!(callback.verifier_for(&self.token)).to_owned();
+        Ok(self.authorize_with_verifier(verifier))
+    }
+
+    /// Accepts the verifier entered by the user for the `"oob"` callback.
+    pub fn authorize_with_verifier<V: Into<String>>(self, verifier: V) -> AuthorizedRequestToken {
+        AuthorizedRequestToken { token: self.token, secret: self.secret, verifier: verifier.into() }
+    }
+}
+
+/// Temporary credentials with `oauth_verifier`, which can be exchanged for an access token.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct AuthorizedRequestToken {
+    token: String,
+    secret: TokenSecret<'static>,
+    verifier: String
+}
+
+impl AuthorizedRequestToken {
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    pub fn verifier(&self) -> &str {
+        &self.verifier
+    }
+
+    /// Creates a builder for POST Token Request with the request token and `oauth_verifier`.
+    pub fn access_token_request<'a, C, S>(&'a self, url: &'a Url, consumer_key: C, consumer_secret: S, signature_method: SignatureMethod)
+        -> OAuthAuthorizationHeaderBuilder<'a>
+        where C: Into<Cow<'a, str>>, S: Into<ConsumerSecret<'a>>
+    {
+        let mut builder = OAuthAuthorizationHeaderBuilder::new("POST", url, consumer_key, consumer_secret, signature_method);
+        builder
+            .token(&self.token[..], &self.secret)
+            .verifier(&self.verifier[..]);
+        builder
+    }
+
+    /// Finishes the flow with the response of `access_token_request`.
+    pub fn into_access_token(self, res: &OAuthTokenResponse) -> AccessToken {
+        AccessToken { token: res.token.clone(), secret: res.token_secret.clone() }
+    }
+}
+
+/// Token credentials to access the protected resources.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct AccessToken {
+    token: String,
+    secret: TokenSecret<'static>
+}
+
+impl AccessToken {
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    pub fn secret(&self) -> &TokenSecret<'static> {
+        &self.secret
+    }
+
+    /// Creates a builder for a request to the protected resource.
+    pub fn request<'a, M, C, S>(&'a self, method: M, url: &'a Url, consumer_key: C, consumer_secret: S, signature_method: SignatureMethod)
+        -> OAuthAuthorizationHeaderBuilder<'a>
+        where M: Into<Cow<'a, str>>, C: Into<Cow<'a, str>>, S: Into<ConsumerSecret<'a>>
+    {
+        let mut builder = OAuthAuthorizationHeaderBuilder::new(method, url, consumer_key, consumer_secret, signature_method);
+        builder.token(&self.token[..], &self.secret);
+        builder
+    }
+
+    pub fn into_credentials(self) -> TokenCredentials {
+        TokenCredentials { token: self.token, secret: self.secret }
+    }
+}
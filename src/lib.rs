@@ -20,24 +20,78 @@
 //! If you use for Twitter, because of Twitter's bug, use `finish_for_twitter` method,
 //! and make sure to encode the request body with `OAUTH_ENCODE_SET`.
 //! For more detail, see [this article](http://azyobuzin.hatenablog.com/entry/2015/04/18/232516) (Japanese).
+//!
+//! # `no_std`
+//! Disabling the default `std` feature makes this crate depend only on `core` and `alloc`.
+//! Then only `SignatureMethod` and the `signing` module are available,
+//! and the caller supplies the timestamp and the nonce.
 
-extern crate base64;
-extern crate rand;
-extern crate ring;
-extern crate time;
-pub extern crate url;
-
-#[cfg(test)] mod tests;
+#![cfg_attr(not(feature = "std"), no_std)]
 
-use std::ascii::AsciiExt;
-use std::borrow::{Borrow, Cow};
-use std::error::Error;
-use std::fmt::{self, Write};
-use std::iter;
-use url::Url;
-use url::percent_encoding::{EncodeSet, PercentEncode, utf8_percent_encode};
+extern crate alloc;
+extern crate base64;
+#[cfg(feature = "std")] extern crate fs2;
+#[cfg(feature = "rustcrypto")] extern crate hmac;
+#[cfg(feature = "openssl")] extern crate openssl;
+#[cfg(feature = "std")] extern crate rand;
+#[cfg(feature = "ring")] extern crate ring;
+#[cfg(feature = "rustcrypto")] extern crate rsa;
+#[cfg(feature = "sqlite")] #[macro_use] extern crate rusqlite;
+#[cfg(feature = "serde")] #[macro_use] extern crate serde;
+#[cfg(feature = "rustcrypto")] extern crate sha1;
+#[cfg(feature = "rustcrypto")] extern crate sha2;
+#[cfg(feature = "std")] extern crate time;
+#[cfg(all(feature = "std", feature = "tracing"))] #[macro_use] extern crate tracing;
+#[cfg(feature = "std")] pub extern crate url;
+extern crate zeroize;
+
+#[cfg(feature = "std")] mod callback;
+#[cfg(feature = "std")] mod challenge;
+mod credentials;
+pub mod crypto;
+#[cfg(feature = "std")] pub mod diagnosis;
+#[cfg(feature = "std")] pub mod echo;
+#[cfg(feature = "std")] pub mod flow;
+#[cfg(feature = "std")] pub mod multipart;
+#[cfg(feature = "std")] mod problem;
+#[cfg(feature = "std")] pub mod provider;
+mod secret;
+#[cfg(feature = "serde")] mod serialization;
+#[cfg(feature = "std")] mod session;
+#[cfg(feature = "std")] pub mod store;
+pub mod signer;
+pub mod signing;
+#[cfg(feature = "sqlite")] pub mod sqlite;
+#[cfg(all(test, feature = "std"))] mod tests;
+#[cfg(feature = "std")] mod token;
+#[cfg(feature = "std")] pub mod twitter;
+#[cfg(feature = "std")] mod verify;
+
+#[cfg(feature = "std")] pub use callback::{OAuthCallback, ParseOAuthCallbackError};
+#[cfg(feature = "std")] pub use challenge::{OAuthChallenge, OAuthChallengeBuilder};
+pub use credentials::{ClientCredentials, TokenCredentials};
+pub use secret::{ConsumerSecret, PrivateKey, TokenSecret};
+#[cfg(feature = "std")] pub use problem::{OAuthProblem, OAuthProblemReport, ParseOAuthProblemError};
+#[cfg(feature = "std")] pub use session::OAuthSession;
+#[cfg(feature = "std")] pub use token::{OAuthTokenResponse, ParseOAuthTokenResponseError};
+#[cfg(feature = "std")] pub use verify::{SignedRequest, VerifyError, VerifyOptions};
+
+use alloc::fmt;
+use alloc::str::FromStr;
+#[cfg(feature = "std")] use std::ascii::AsciiExt;
+#[cfg(feature = "std")] use std::borrow::{Borrow, Cow};
+#[cfg(feature = "std")] use std::error::Error;
+#[cfg(feature = "std")] use std::fmt::Write;
+#[cfg(feature = "std")] use std::iter;
+#[cfg(feature = "std")] use url::Url;
+#[cfg(feature = "std")] use url::percent_encoding::EncodeSet;
+#[cfg(feature = "std")] use signing::{PercentEncode, PercentEncodedParameters, format_auth_params};
+#[cfg(feature = "std")] use signing::{percent_encode_byte_parameters, str_to_bytes};
+#[cfg(all(test, feature = "std"))] use signing::{normalize_parameters, percent_encode_parameters};
 
 /// Available `oauth_signature_method` types.
+///
+/// Other signature methods such as RSA-SHA1 are available with `signer::SignerRegistry`.
 #[derive(Copy, Debug, PartialEq, Eq, Clone, Hash)]
 pub enum SignatureMethod {
     /// HMAC-SHA1
@@ -61,25 +115,61 @@ impl fmt::Display for SignatureMethod {
     }
 }
 
+/// Parses the name of `oauth_signature_method`, which is case-sensitive.
+///
+/// # Example
+/// ```
+/// # use oauthcli::SignatureMethod;
+/// assert_eq!("HMAC-SHA1".parse(), Ok(SignatureMethod::HmacSha1));
+/// assert!("hmac-sha1".parse::<SignatureMethod>().is_err());
+/// ```
+impl FromStr for SignatureMethod {
+    type Err = ParseSignatureMethodError;
+
+    fn from_str(s: &str) -> Result<SignatureMethod, ParseSignatureMethodError> {
+        match s {
+            "HMAC-SHA1" => Ok(SignatureMethod::HmacSha1),
+            "PLAINTEXT" => Ok(SignatureMethod::Plaintext),
+            _ => Err(ParseSignatureMethodError)
+        }
+    }
+}
+
+/// The signature method is not built-in.
+#[derive(Copy, Debug, PartialEq, Eq, Clone, Hash)]
+pub struct ParseSignatureMethodError;
+
+#[cfg(feature = "std")]
+impl Error for ParseSignatureMethodError {
+    fn description(&self) -> &str {
+        "The signature method is not built-in"
+    }
+}
+
+impl fmt::Display for ParseSignatureMethodError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("The signature method is not built-in")
+    }
+}
+
 /// [RFC 5849 section 3.6](http://tools.ietf.org/html/rfc5849#section-3.6).
 #[derive(Copy, Clone)]
 #[allow(non_camel_case_types)]
 pub struct OAUTH_ENCODE_SET;
 
+#[cfg(feature = "std")]
 impl EncodeSet for OAUTH_ENCODE_SET {
     fn contains(&self, byte: u8) -> bool {
-        !((byte >= 0x30 && byte <= 0x39)
-        || (byte >= 0x41 && byte <= 0x5A)
-        || (byte >= 0x61 && byte <= 0x7A)
-        || byte == 0x2D || byte == 0x2E
-        || byte == 0x5F || byte == 0x7E)
+        !signing::is_unreserved(byte)
     }
 }
 
-fn percent_encode(input: &str) -> PercentEncode<OAUTH_ENCODE_SET> {
-    utf8_percent_encode(input, OAUTH_ENCODE_SET)
+#[cfg(feature = "std")]
+fn percent_encode(input: &str) -> PercentEncode {
+    signing::percent_encode(input.as_bytes())
 }
 
+#[cfg(feature = "std")]
 #[derive(Copy, Debug, PartialEq, Eq, Clone, Hash)]
 pub enum ParseOAuthAuthorizationHeaderError {
     /// The input is violating auth-param format.
@@ -88,6 +178,7 @@ pub enum ParseOAuthAuthorizationHeaderError {
     EscapeError
 }
 
+#[cfg(feature = "std")]
 impl Error for ParseOAuthAuthorizationHeaderError {
     fn description(&self) -> &str {
         match *self {
@@ -97,6 +188,7 @@ impl Error for ParseOAuthAuthorizationHeaderError {
     }
 }
 
+#[cfg(feature = "std")]
 impl fmt::Display for ParseOAuthAuthorizationHeaderError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.write_str(self.description())
@@ -111,11 +203,13 @@ impl fmt::Display for ParseOAuthAuthorizationHeaderError {
 /// let header: OAuthAuthorizationHeader = "oauth_consumer_key=\"foo\"".parse().unwrap();
 /// assert_eq!(header.to_string(), "OAuth oauth_consumer_key=\"foo\"");
 /// ```
+#[cfg(feature = "std")]
 #[derive(Debug, Clone)]
 pub struct OAuthAuthorizationHeader {
     s: String
 }
 
+#[cfg(feature = "std")]
 impl OAuthAuthorizationHeader {
     /// `auth-param` in RFC 7235
     pub fn auth_param(&self) -> &str {
@@ -127,6 +221,7 @@ impl OAuthAuthorizationHeader {
     }
 }
 
+#[cfg(feature = "std")]
 impl fmt::Display for OAuthAuthorizationHeader {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         try!(f.write_str("OAuth "));
@@ -134,118 +229,185 @@ impl fmt::Display for OAuthAuthorizationHeader {
     }
 }
 
+#[cfg(feature = "std")]
 impl std::str::FromStr for OAuthAuthorizationHeader {
     type Err = ParseOAuthAuthorizationHeaderError;
 
     fn from_str(s: &str) -> Result<OAuthAuthorizationHeader, ParseOAuthAuthorizationHeaderError> {
-        fn is_hex(x: u8) -> bool {
-            (x >= b'0' && x <= b'9') ||
-            (x >= b'A' && x <= b'F') ||
-            (x >= b'a' && x <= b'f')
+        let s = remove_oauth_scheme(s);
+        try!(parse_auth_params(s, is_realm));
+        Ok(OAuthAuthorizationHeader { s: s.to_owned() })
+    }
+}
+
+/// `realm` is the only parameter which is not percent-encoded in `Authorization` header.
+#[cfg(feature = "std")]
+fn is_realm(name: &str) -> bool {
+    name == "realm"
+}
+
+/// Removes "OAuth" scheme from `s` if it exists.
+#[cfg(feature = "std")]
+fn remove_oauth_scheme(s: &str) -> &str {
+    let s = s.trim();
+    if let Some(scheme) = s.split_whitespace().next() {
+        if scheme.eq_ignore_ascii_case("OAuth") {
+            return s[5..].trim_left();
         }
+    }
+    s
+}
 
-        fn check(s: &str) -> bool {
-            let mut i = s.as_bytes().iter();
-            while let Some(&c) = i.next() {
-                match c {
-                    0x25 => {
-                        if let (Some(&a), Some(&b)) = (i.next(), i.next()) {
-                            if !(is_hex(a) && is_hex(b)) { return false; }
-                        } else {
-                            return false;
-                        }
-                    },
-                    c => if OAUTH_ENCODE_SET.contains(c) { return false; }
-                }
+/// Splits `auth-param`s into decoded key-value pairs.
+///
+/// The values of the parameters for which `is_plain` returns `true`, such as `realm`,
+/// are RFC 2617 quoted-strings. The names and the other values must be encoded with `OAUTH_ENCODE_SET`.
+#[cfg(feature = "std")]
+fn parse_auth_params<F>(s: &str, is_plain: F) -> Result<Vec<(String, String)>, ParseOAuthAuthorizationHeaderError>
+    where F: Fn(&str) -> bool
+{
+    fn is_hex(x: u8) -> bool {
+        (x >= b'0' && x <= b'9') ||
+        (x >= b'A' && x <= b'F') ||
+        (x >= b'a' && x <= b'f')
+    }
+
+    fn check(s: &str) -> bool {
+        let mut i = s.as_bytes().iter();
+        while let Some(&c) = i.next() {
+            match c {
+                0x25 => {
+                    if let (Some(&a), Some(&b)) = (i.next(), i.next()) {
+                        if !(is_hex(a) && is_hex(b)) { return false; }
+                    } else {
+                        return false;
+                    }
+                },
+                c => if OAUTH_ENCODE_SET.contains(c) { return false; }
             }
+        }
+
+        true
+    }
+
+    fn decode(s: &str) -> Result<String, ParseOAuthAuthorizationHeaderError> {
+        url::percent_encoding::percent_decode(s.as_bytes()).decode_utf8()
+            .map(|x| x.into_owned())
+            .map_err(|_| ParseOAuthAuthorizationHeaderError::EscapeError)
+    }
 
-            true
+    let mut result = Vec::new();
+    let mut rest = s;
+
+    loop {
+        // Skip empty elements of the list
+        rest = rest.trim_left_matches(|c: char| c == ',' || c.is_whitespace());
+        if rest.len() == 0 { break; }
+
+        let equal_index = try!(rest.find('=').ok_or(ParseOAuthAuthorizationHeaderError::FormatError));
+        let key = rest[0..equal_index].trim_right();
+        if key.len() == 0 {
+            return Err(ParseOAuthAuthorizationHeaderError::FormatError);
+        }
+        if !check(key) {
+            return Err(ParseOAuthAuthorizationHeaderError::EscapeError);
         }
 
-        // Remove "OAuth" scheme
-        let mut s = s.trim();
-        if let Some(scheme) = s.split_whitespace().next() {
-            if scheme.eq_ignore_ascii_case("OAuth") {
-                s = &scheme[5..].trim_left();
-            }
+        rest = rest[equal_index+1..].trim_left();
+        if !rest.starts_with('"') {
+            return Err(ParseOAuthAuthorizationHeaderError::FormatError);
         }
 
-        // Check each pair
-        for pair in s.split(',').map(|x| x.trim()).filter(|x| x.len() > 0) {
-            if let Some(equal_index) = pair.find('=') {
-                if !check(pair[0..equal_index].trim_right()) {
-                    return Err(ParseOAuthAuthorizationHeaderError::EscapeError);
-                }
-
-                let val = pair[equal_index+1..].trim_left();
-                if !val.starts_with('"') || !val.ends_with('"') {
-                    return Err(ParseOAuthAuthorizationHeaderError::FormatError);
-                }
-                if !check(&val[1..val.len()-1]) {
-                    return Err(ParseOAuthAuthorizationHeaderError::EscapeError);
-                }
-            } else {
-                return Err(ParseOAuthAuthorizationHeaderError::FormatError);
+        // quoted-string, which may contain commas and quoted-pairs
+        let mut val = String::new();
+        let mut end = None;
+        let mut chars = rest.char_indices().skip(1);
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => { end = Some(i); break; },
+                '\\' => match chars.next() {
+                    Some((_, x)) => val.push(x),
+                    None => break
+                },
+                c => val.push(c)
             }
         }
+        let end = try!(end.ok_or(ParseOAuthAuthorizationHeaderError::FormatError));
 
-        Ok(OAuthAuthorizationHeader { s: s.to_owned() })
-    }
-}
-
-fn base_string_url(url: &Url) -> String {
-    let scheme = url.scheme();
-
-    let mut result = String::with_capacity(url.as_str().len());
-    result.push_str(scheme);
-    result.push_str("://");
-    result.push_str(url.host_str().expect("The host is None"));
-
-    if let Some(p) = url.port() {
-        match (scheme, p) {
-            ("http", 80) | ("https", 443) => (),
-            ("http", p) | ("https", p) => write!(&mut result, ":{}", p).unwrap(),
-            _ => panic!("The scheme is not \"http\" or \"https\"")
+        rest = rest[end+1..].trim_left();
+        if rest.len() > 0 && !rest.starts_with(',') {
+            return Err(ParseOAuthAuthorizationHeaderError::FormatError);
         }
-    }
 
-    result.push_str(url.path());
-    result
-}
+        let key = try!(decode(key));
+        let val =
+            if is_plain(&key) { val }
+            else if check(&val) { try!(decode(&val)) }
+            else { return Err(ParseOAuthAuthorizationHeaderError::EscapeError); };
 
-struct PercentEncodedParameters<'a>(Vec<(Cow<'a, str>, Cow<'a, str>)>);
+        result.push((key, val));
+    }
 
-fn percent_encode_parameters<'a, P>(params: P) -> PercentEncodedParameters<'a>
-    where P: Iterator<Item = (Cow<'a, str>, Cow<'a, str>)>
-{
-    PercentEncodedParameters(
-        params
-            .map(|(k, v)| (percent_encode(&k).to_string().into(), percent_encode(&v).to_string().into()))
-            .collect()
-    )
+    Ok(result)
 }
 
-fn normalize_parameters<'a>(params: PercentEncodedParameters<'a>) -> String {
-    let mut mutparams = params.0;
+/// Encodes key-value pairs as `application/x-www-form-urlencoded` with `OAUTH_ENCODE_SET`.
+#[cfg(feature = "std")]
+fn format_form_parameters<'a, P>(params: P) -> String
+    where P: Iterator<Item = (&'a str, &'a str)>
+{
     let mut result = String::new();
 
-    if mutparams.len() > 0 {
-        mutparams.sort();
+    for (k, v) in params {
+        if result.len() > 0 { result.push('&'); }
 
-        let mut first = true;
-        for (key, val) in mutparams.into_iter() {
-            if first { first = false; }
-            else { result.push('&'); }
-
-            result.push_str(&key);
-            result.push('=');
-            result.push_str(&val);
-        }
+        write!(&mut result, "{}={}",
+            percent_encode(k), percent_encode(v)).unwrap();
     }
 
     result
 }
 
+#[cfg(feature = "std")]
+fn base_string_url(url: &Url) -> String {
+    signing::base_string_uri(url.scheme(), url.host_str().expect("The host is None"), url.port(), url.path())
+}
+
+/// Decodes `application/x-www-form-urlencoded` body into byte sequences.
+///
+/// Unlike `url::form_urlencoded::parse`, this function does not assume that the body is UTF-8,
+/// so the result can be passed to `request_byte_parameters` to sign a body in a legacy encoding
+/// such as Shift_JIS ([RFC 5849 section 3.4.1.3.1](https://tools.ietf.org/html/rfc5849#section-3.4.1.3.1)).
+///
+/// # Example
+/// ```
+/// # use oauthcli::decode_form_body;
+/// assert_eq!(
+///     decode_form_body(b"name=%83e%83X%83g&a+b"),
+///     vec![(b"name".to_vec(), b"\x83e\x83X\x83g".to_vec()), (b"a b".to_vec(), Vec::new())]
+/// );
+/// ```
+#[cfg(feature = "std")]
+pub fn decode_form_body(input: &[u8]) -> Vec<(Vec<u8>, Vec<u8>)> {
+    fn decode(input: &[u8]) -> Vec<u8> {
+        let replaced: Vec<u8> = input.iter()
+            .map(|&x| if x == b'+' { b' ' } else { x })
+            .collect();
+        url::percent_encoding::percent_decode(&replaced).collect()
+    }
+
+    input.split(|&x| x == b'&')
+        .filter(|x| x.len() > 0)
+        .map(|pair| {
+            let mut pair_iter = pair.splitn(2, |&x| x == b'=');
+            let key = pair_iter.next().unwrap();
+            let val = pair_iter.next().unwrap_or(b"");
+            (decode(key), decode(val))
+        })
+        .collect()
+}
+
+#[cfg(feature = "std")]
 fn gen_timestamp() -> u64 {
     let x = time::now_utc().to_timespec().sec;
     assert!(x > 0);
@@ -253,6 +415,7 @@ fn gen_timestamp() -> u64 {
 }
 
 /// Generate a string for `oauth_nonce`.
+#[cfg(feature = "std")]
 fn nonce() -> String {
     use rand::Rng;
 
@@ -260,37 +423,166 @@ fn nonce() -> String {
         .take(42).collect()
 }
 
-fn hmac_sha1_base64(key: &[u8], msg: &[u8]) -> String {
-    use ring::{digest, hmac};
+/// Request body classified by `Content-Type`.
+#[cfg(feature = "std")]
+enum RequestBody {
+    /// Parameters of `application/x-www-form-urlencoded` body, which are signed.
+    Form(Vec<(Vec<u8>, Vec<u8>)>),
+    /// `oauth_body_hash` of any other body.
+    Hash(String),
+    /// `multipart/form-data` body, whose parts and the request parameters are not signed.
+    Multipart
+}
+
+#[cfg(feature = "std")]
+impl RequestBody {
+    fn new(content_type: Option<&str>, body: &[u8]) -> RequestBody {
+        let media_type = content_type
+            .and_then(|x| x.split(';').next())
+            .map(|x| x.trim());
+        let is = |x: &str| media_type.map_or(false, |y| y.eq_ignore_ascii_case(x));
+
+        if is("application/x-www-form-urlencoded") {
+            RequestBody::Form(decode_form_body(body))
+        } else if is("multipart/form-data") {
+            RequestBody::Multipart
+        } else {
+            RequestBody::Hash(signing::body_hash(body))
+        }
+    }
+}
+
+/// Calculates `oauth_signature`.
+/// `params` are the protocol parameters and the request parameters except the query of `url`.
+///
+/// # Panics
+/// This function will panic if `signer` fails, which `registered_signature_method` checks in advance.
+#[cfg(feature = "std")]
+fn signature<'a, P>(signer: &signer::Signer, method: &str, url: &'a Url, params: P,
+    consumer_secret: &str, token_secret: Option<&str>, for_twitter: bool) -> String
+    where P: Iterator<Item = (Cow<'a, [u8]>, Cow<'a, [u8]>)>
+{
+    let base_string =
+        if signer.uses_base_string() { signature_base_string(method, url, params, for_twitter) }
+        else { String::new() };
+
+    signer.sign(&base_string, consumer_secret, token_secret)
+        .expect("Failed to sign the request")
+}
+
+/// Calculates Signature Base String.
+/// `params` are the protocol parameters and the request parameters except the query of `url`.
+#[cfg(feature = "std")]
+fn signature_base_string<'a, P>(method: &str, url: &'a Url, params: P, for_twitter: bool) -> String
+    where P: Iterator<Item = (Cow<'a, [u8]>, Cow<'a, [u8]>)>
+{
+    let params = signature_parameters(url, params, for_twitter);
+    let base_string_url = base_string_url(url);
+
+    #[cfg(feature = "tracing")]
+    {
+        let mut names: Vec<&str> = params.0.iter().map(|&(ref k, _)| &k[..]).collect();
+        names.sort();
+        debug!(base_string_url = %base_string_url, parameters = ?names, "Calculating the Signature Base String");
+    }
+
+    signing::encoded_signature_base_string(method, &base_string_url, params)
+}
 
-    base64::encode(
-        hmac::sign(
-            &hmac::SigningKey::new(&digest::SHA1, key),
-            msg
-        ).as_ref()
-    )
+/// Percent-encodes `params` and the query of `url`.
+#[cfg(feature = "std")]
+fn signature_parameters<'a, P>(url: &'a Url, params: P, for_twitter: bool) -> PercentEncodedParameters<'a>
+    where P: Iterator<Item = (Cow<'a, [u8]>, Cow<'a, [u8]>)>
+{
+    if for_twitter {
+        // Workaround for Twitter: don't re-encode the query
+        let PercentEncodedParameters(mut x) = percent_encode_byte_parameters(params);
+
+        if let Some(query) = url.query() {
+            for pair in query.split('&').filter(|x| x.len() > 0) {
+                let mut pair_iter = pair.splitn(2, '=');
+                let key = pair_iter.next().unwrap();
+                let val = pair_iter.next().unwrap_or("");
+                x.push((key.into(), val.into()));
+            }
+        }
+
+        PercentEncodedParameters(x)
+    } else {
+        percent_encode_byte_parameters(params.chain(
+            url.query_pairs().map(|(k, v)| (str_to_bytes(k), str_to_bytes(v)))))
+    }
 }
 
+/// The name of the way to calculate the signature, which is recorded in the spans.
+#[cfg(all(feature = "std", feature = "tracing"))]
+fn quirks_profile(for_twitter: bool) -> &'static str {
+    if for_twitter { "twitter" } else { "rfc5849" }
+}
+
+/// Parameters in `Authorization` header which are generated by `OAuthAuthorizationHeaderBuilder`.
+#[cfg(feature = "std")]
+const RESERVED_HEADER_PARAMETERS: &'static [&'static str] = &[
+    "realm", "oauth_consumer_key", "oauth_token", "oauth_signature_method",
+    "oauth_timestamp", "oauth_nonce", "oauth_callback", "oauth_verifier",
+    "oauth_session_handle", "oauth_version", "oauth_signature"
+];
+
+#[cfg(feature = "std")]
+#[derive(Copy, Debug, PartialEq, Eq, Clone, Hash)]
+pub enum HeaderParameterError {
+    /// The parameter is generated by the builder.
+    Reserved,
+    /// The parameter has already been added.
+    Duplicated,
+    /// The name does not start with `xoauth_`.
+    NotExtension
+}
+
+#[cfg(feature = "std")]
+impl Error for HeaderParameterError {
+    fn description(&self) -> &str {
+        match *self {
+            HeaderParameterError::Reserved => "The parameter is generated by the builder",
+            HeaderParameterError::Duplicated => "The parameter has already been added",
+            HeaderParameterError::NotExtension => "The name does not start with `xoauth_`"
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl fmt::Display for HeaderParameterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.description())
+    }
+}
+
+#[cfg(feature = "std")]
+#[derive(Clone)]
 pub struct OAuthAuthorizationHeaderBuilder<'a> {
     method: Cow<'a, str>,
     url: &'a Url,
-    parameters: Vec<(Cow<'a, str>, Cow<'a, str>)>,
+    parameters: Vec<(Cow<'a, [u8]>, Cow<'a, [u8]>)>,
     consumer_key: Cow<'a, str>,
-    consumer_secret: Cow<'a, str>,
-    signature_method: SignatureMethod,
+    consumer_secret: ConsumerSecret<'a>,
+    signature_method: Cow<'a, str>,
+    signer: &'a signer::Signer,
     realm: Option<Cow<'a, str>>,
     token: Option<Cow<'a, str>>,
-    token_secret: Option<Cow<'a, str>>,
+    token_secret: Option<TokenSecret<'a>>,
     timestamp: Option<u64>,
     nonce: Option<Cow<'a, str>>,
     callback: Option<Cow<'a, str>>,
     verifier: Option<Cow<'a, str>>,
+    session_handle: Option<Cow<'a, str>>,
+    extension_parameters: Vec<(Cow<'a, str>, Cow<'a, str>)>,
     include_version: bool
 }
 
+#[cfg(feature = "std")]
 impl<'a> OAuthAuthorizationHeaderBuilder<'a> {
     pub fn new<M, C, S>(method: M, url: &'a Url, consumer_key: C, consumer_secret: S, signature_method: SignatureMethod) -> Self
-        where M: Into<Cow<'a, str>>, C: Into<Cow<'a, str>>, S: Into<Cow<'a, str>>
+        where M: Into<Cow<'a, str>>, C: Into<Cow<'a, str>>, S: Into<ConsumerSecret<'a>>
     {
         OAuthAuthorizationHeaderBuilder {
             method: method.into(),
@@ -298,7 +590,8 @@ impl<'a> OAuthAuthorizationHeaderBuilder<'a> {
             parameters: Vec::new(),
             consumer_key: consumer_key.into(),
             consumer_secret: consumer_secret.into(),
-            signature_method: signature_method,
+            signature_method: signature_method.to_str().into(),
+            signer: signature_method.signer(),
             realm: None,
             token: None,
             token_secret: None,
@@ -306,12 +599,24 @@ impl<'a> OAuthAuthorizationHeaderBuilder<'a> {
             nonce: None,
             callback: None,
             verifier: None,
+            session_handle: None,
+            extension_parameters: Vec::new(),
             include_version: true
         }
     }
 
     pub fn request_parameters<K, V, P>(&mut self, parameters: P) -> &mut Self
         where K: Into<Cow<'a, str>>, V: Into<Cow<'a, str>>, P: IntoIterator<Item=(K, V)>
+    {
+        self.parameters.extend(parameters.into_iter().map(|(k, v)| (str_to_bytes(k.into()), str_to_bytes(v.into()))));
+        self
+    }
+
+    /// Adds request parameters as byte sequences, which may not be UTF-8.
+    ///
+    /// Use `decode_form_body` to get the parameters from a form-encoded body.
+    pub fn request_byte_parameters<K, V, P>(&mut self, parameters: P) -> &mut Self
+        where K: Into<Cow<'a, [u8]>>, V: Into<Cow<'a, [u8]>>, P: IntoIterator<Item=(K, V)>
     {
         self.parameters.extend(parameters.into_iter().map(|(k, v)| (k.into(), v.into())));
         self
@@ -323,7 +628,7 @@ impl<'a> OAuthAuthorizationHeaderBuilder<'a> {
     }
 
     pub fn token<T, S>(&mut self, token: T, secret: S) -> &mut Self
-        where T: Into<Cow<'a, str>>, S: Into<Cow<'a, str>>
+        where T: Into<Cow<'a, str>>, S: Into<TokenSecret<'a>>
     {
         self.token = Some(token.into());
         self.token_secret = Some(secret.into());
@@ -354,6 +659,101 @@ impl<'a> OAuthAuthorizationHeaderBuilder<'a> {
         self
     }
 
+    /// Sets `oauth_session_handle` of [OAuth Session 1.0](http://oauth.googlecode.com/svn/spec/ext/session/1.0/drafts/1/spec.html)
+    /// to refresh the access token.
+    pub fn session_handle<T: Into<Cow<'a, str>>>(&mut self, session_handle: T) -> &mut Self {
+        self.session_handle = Some(session_handle.into());
+        self
+    }
+
+    /// Adds an extension parameter such as `oauth_body_hash` or `xoauth_displayname`,
+    /// which is signed and included in the header.
+    ///
+    /// Returns `Err` if `name` is a parameter that the builder generates,
+    /// or if `name` has already been added.
+    ///
+    /// # Example
+    /// ```
+    /// # extern crate oauthcli;
+    /// # extern crate url;
+    /// # use oauthcli::*;
+    /// # fn main() {
+    /// let url = url::Url::parse("http://example.com/").unwrap();
+    /// let mut builder = OAuthAuthorizationHeaderBuilder::new(
+    ///     "GET", &url, "consumer", "secret", SignatureMethod::HmacSha1);
+    ///
+    /// assert!(builder.header_parameter("oauth_body_hash", "2jmj7l5rSw0yVb/vlWAYkK/YBwk=").is_ok());
+    /// assert_eq!(builder.header_parameter("oauth_nonce", "nonce").err(), Some(HeaderParameterError::Reserved));
+    /// # }
+    /// ```
+    pub fn header_parameter<K, V>(&mut self, name: K, value: V) -> Result<&mut Self, HeaderParameterError>
+        where K: Into<Cow<'a, str>>, V: Into<Cow<'a, str>>
+    {
+        let name = name.into();
+
+        if name.len() == 0 || RESERVED_HEADER_PARAMETERS.contains(&name.borrow()) {
+            return Err(HeaderParameterError::Reserved);
+        }
+        if self.extension_parameters.iter().any(|&(ref k, _)| k == &name) {
+            return Err(HeaderParameterError::Duplicated);
+        }
+
+        self.extension_parameters.push((name, value.into()));
+        Ok(self)
+    }
+
+    /// Adds a `xoauth_` extension parameter, which is signed and included in the header.
+    /// Fails if `name` does not start with `"xoauth_"`, or if `name` has already been added.
+    pub fn xoauth_parameter<K, V>(&mut self, name: K, value: V) -> Result<&mut Self, HeaderParameterError>
+        where K: Into<Cow<'a, str>>, V: Into<Cow<'a, str>>
+    {
+        let name = name.into();
+        if !name.starts_with("xoauth_") {
+            return Err(HeaderParameterError::NotExtension);
+        }
+        self.header_parameter(name, value)
+    }
+
+    /// Sets `xoauth_requestor_id` for two-legged OAuth,
+    /// where a consumer makes a request on behalf of the user without a token.
+    ///
+    /// # Example
+    /// ```
+    /// # extern crate oauthcli;
+    /// # extern crate url;
+    /// # use oauthcli::*;
+    /// # fn main() {
+    /// let url = url::Url::parse("http://example.com/feeds/default").unwrap();
+    /// let header =
+    ///     OAuthAuthorizationHeaderBuilder::new(
+    ///         "GET", &url, "consumer", "secret", SignatureMethod::HmacSha1)
+    ///     .requestor_id("john.doe@example.com")
+    ///     .finish();
+    /// # }
+    /// ```
+    pub fn requestor_id<T: Into<Cow<'a, str>>>(&mut self, requestor_id: T) -> &mut Self {
+        self.extension_parameters.retain(|&(ref k, _)| k != "xoauth_requestor_id");
+        self.extension_parameters.push(("xoauth_requestor_id".into(), requestor_id.into()));
+        self
+    }
+
+    /// Uses the signer registered as `name` in `registry`
+    /// instead of the signature method passed to `new`.
+    ///
+    /// Returns `Err` if `name` is not registered, or the signer fails to sign,
+    /// e.g. `RsaSha1Signer` with the `ring` crypto backend.
+    pub fn registered_signature_method<N>(&mut self, registry: &'a signer::SignerRegistry, name: N)
+        -> Result<&mut Self, signer::SignerError>
+        where N: Into<Cow<'a, str>>
+    {
+        let name = name.into();
+        let signer = try!(registry.get(&name).ok_or(signer::SignerError::NotRegistered));
+        try!(signer.sign("", "", None).map_err(signer::SignerError::Crypto));
+        self.signer = signer;
+        self.signature_method = name;
+        Ok(self)
+    }
+
     /// Sets the value that indicates whether the builder includes `"oauth_version"` parameter.
     /// The default is `true`.
     pub fn include_version(&mut self, include_version: bool) -> &mut Self {
@@ -361,96 +761,85 @@ impl<'a> OAuthAuthorizationHeaderBuilder<'a> {
         self
     }
 
-    fn signature(&self, oauth_params: &[(&'a str, &'a str)], for_twitter: bool) -> String {
-        let mut key: String = percent_encode(&self.consumer_secret).collect();
-        key.push('&');
+    fn signature(&self, oauth_params: &[(&'a str, &'a str)], body: Option<&RequestBody>, for_twitter: bool) -> String {
+        let (request_params, body_params): (&[_], &[(Vec<u8>, Vec<u8>)]) = match body {
+            Some(&RequestBody::Form(ref x)) => (&self.parameters, x),
+            Some(&RequestBody::Multipart) => (&[], &[]),
+            _ => (&self.parameters, &[])
+        };
 
-        if let &Some(ref x) = &self.token_secret {
-            key.extend(percent_encode(&x));
-        }
+        let params = oauth_params.iter()
+            .map(|&(k, v)| (k.as_bytes().into(), v.as_bytes().into()))
+            .chain(request_params.iter()
+                .map(|&(ref k, ref v)| (Cow::Borrowed(k.borrow()), Cow::Borrowed(v.borrow()))))
+            .chain(body_params.iter()
+                .map(|&(ref k, ref v)| (Cow::Borrowed(&k[..]), Cow::Borrowed(&v[..]))));
+
+        signature(
+            self.signer, &self.method, self.url, params,
+            self.consumer_secret.expose(), self.token_secret.as_ref().map(|x| x.expose()),
+            for_twitter
+        )
+    }
 
-        match &self.signature_method {
-            &SignatureMethod::HmacSha1 => {
-                let params = oauth_params.iter()
-                    .map(|&(k, v)| (k.into(), v.into()))
-                    .chain(self.parameters.iter()
-                        .map(|&(ref k, ref v)| (Cow::Borrowed(k.borrow()), Cow::Borrowed(v.borrow()))));
-
-                let params =
-                    if for_twitter {
-                        // Workaround for Twitter: don't re-encode the query
-                        let PercentEncodedParameters(mut x) = percent_encode_parameters(params);
-
-                        if let Some(query) = self.url.query() {
-                            for pair in query.split('&').filter(|x| x.len() > 0) {
-                                let mut pair_iter = pair.splitn(2, '=');
-                                let key = pair_iter.next().unwrap();
-                                let val = pair_iter.next().unwrap_or("");
-                                x.push((key.into(), val.into()));
-                            }
-                        }
-
-                        PercentEncodedParameters(x)
-                    } else {
-                        percent_encode_parameters(params.chain(self.url.query_pairs()))
-                    };
-
-                let mut base_string = self.method.to_ascii_uppercase();
-                base_string.push('&');
-                base_string.extend(percent_encode(&base_string_url(self.url)));
-                base_string.push('&');
-                base_string.extend(percent_encode(&normalize_parameters(params)));
-
-                hmac_sha1_base64(key.as_bytes(), base_string.as_bytes())
-            },
-            &SignatureMethod::Plaintext => key
-        }
+    /// The protocol parameters except `realm` and `oauth_signature`.
+    fn oauth_parameters<'b>(&'b self, timestamp: &'b str, nonce: &'b str, body: Option<&'b RequestBody>) -> Vec<(&'b str, &'b str)> {
+        let mut p: Vec<(&str, &str)> = Vec::with_capacity(10 + self.extension_parameters.len());
+
+        p.push(("oauth_consumer_key", self.consumer_key.borrow()));
+        if let Some(ref x) = self.token { p.push(("oauth_token", x.borrow())) }
+        p.push(("oauth_signature_method", self.signature_method.borrow()));
+        p.push(("oauth_timestamp", timestamp));
+        p.push(("oauth_nonce", nonce));
+        if let &Some(ref x) = &self.callback { p.push(("oauth_callback", x.borrow())) }
+        if let &Some(ref x) = &self.verifier { p.push(("oauth_verifier", x.borrow())) }
+        if let &Some(ref x) = &self.session_handle { p.push(("oauth_session_handle", x.borrow())) }
+        if let Some(&RequestBody::Hash(ref x)) = body { p.push(("oauth_body_hash", x)) }
+        p.extend(self.extension_parameters.iter().map(|&(ref k, ref v)| (k.borrow(), v.borrow())));
+        if self.include_version { p.push(("oauth_version", "1.0")) }
+
+        p
     }
 
-    fn finish_impl(&self, for_twitter: bool) -> OAuthAuthorizationHeader {
+    fn finish_impl(&self, for_twitter: bool, body: Option<&RequestBody>) -> OAuthAuthorizationHeader {
+        #[cfg(feature = "tracing")]
+        let _span = debug_span!("oauth_sign", method = %self.method,
+            signature_method = %self.signature_method, quirks = quirks_profile(for_twitter)).entered();
+
         let tmp_timestamp = self.timestamp.unwrap_or_else(gen_timestamp).to_string();
         let tmp_nonce;
-        let oauth_params = {
-            let mut p = Vec::with_capacity(8);
-
-            p.push(("oauth_consumer_key", self.consumer_key.borrow()));
-            if let Some(ref x) = self.token { p.push(("oauth_token", x.borrow())) }
-            p.push(("oauth_signature_method", self.signature_method.to_str()));
-            p.push(("oauth_timestamp", &tmp_timestamp));
-            p.push(("oauth_nonce", match &self.nonce {
-                &Some(ref x) => x.borrow(),
-                _ => {
-                    tmp_nonce = nonce();
-                    &tmp_nonce
-                }
-            }));
-            if let &Some(ref x) = &self.callback { p.push(("oauth_callback", x.borrow())) }
-            if let &Some(ref x) = &self.verifier { p.push(("oauth_verifier", x.borrow())) }
-            if self.include_version { p.push(("oauth_version", "1.0")) }
-
-            p
+        let oauth_nonce: &str = match &self.nonce {
+            &Some(ref x) => x.borrow(),
+            _ => {
+                tmp_nonce = nonce();
+                &tmp_nonce
+            }
         };
+        let oauth_params = self.oauth_parameters(&tmp_timestamp, oauth_nonce, body);
 
-        let signature = self.signature(&oauth_params, for_twitter);
+        let signature = self.signature(&oauth_params, body, for_twitter);
 
-        let mut oauth_params = self.realm.as_ref()
+        let oauth_params = self.realm.as_ref()
             .map(|x| ("realm", x.borrow()))
             .into_iter()
             .chain(oauth_params.into_iter())
             .chain(iter::once(("oauth_signature", &signature[..])));
 
-        let mut result = String::new();
-        let mut first = true;
+        OAuthAuthorizationHeader { s: format_auth_params(oauth_params) }
+    }
 
-        while let Some((k, v)) = oauth_params.next() {
-            if first { first = false; }
-            else { result.push(','); }
+    fn finish_with_body_impl(&self, content_type: Option<&str>, body: &[u8], for_twitter: bool)
+        -> Result<OAuthAuthorizationHeader, HeaderParameterError>
+    {
+        let body = RequestBody::new(content_type, body);
 
-            write!(&mut result, "{}=\"{}\"",
-                percent_encode(k), percent_encode(v)).unwrap();
+        if let RequestBody::Hash(_) = body {
+            if self.extension_parameters.iter().any(|&(ref k, _)| k == "oauth_body_hash") {
+                return Err(HeaderParameterError::Duplicated);
+            }
         }
 
-        OAuthAuthorizationHeader { s: result }
+        Ok(self.finish_impl(for_twitter, Some(&body)))
     }
 
     /// Generate `Authorization` header for OAuth.
@@ -458,7 +847,41 @@ impl<'a> OAuthAuthorizationHeaderBuilder<'a> {
     /// # Panics
     /// This function will panic if `url` is not valid for HTTP or HTTPS.
     pub fn finish(&self) -> OAuthAuthorizationHeader {
-        self.finish_impl(false)
+        self.finish_impl(false, None)
+    }
+
+    /// Generate `Authorization` header for the request with `body`.
+    ///
+    /// If `content_type` is `application/x-www-form-urlencoded`, the parameters in `body` are signed,
+    /// so don't pass them to `request_parameters`.
+    /// If it is `multipart/form-data`, the body and `request_parameters` are not signed
+    /// as `multipart::sign` does.
+    /// Otherwise, `oauth_body_hash` of [OAuth Request Body Hash](https://tools.ietf.org/id/draft-eaton-oauth-bodyhash-00.html)
+    /// is added.
+    ///
+    /// Returns `Err` if `oauth_body_hash` is required and has already been added by `header_parameter`.
+    ///
+    /// # Panics
+    /// This function will panic if `url` is not valid for HTTP or HTTPS.
+    ///
+    /// # Example
+    /// ```
+    /// # extern crate oauthcli;
+    /// # extern crate url;
+    /// # use oauthcli::*;
+    /// # fn main() {
+    /// let url = url::Url::parse("http://example.com/resource").unwrap();
+    /// let header =
+    ///     OAuthAuthorizationHeaderBuilder::new(
+    ///         "PUT", &url, "consumer", "secret", SignatureMethod::HmacSha1)
+    ///     .token("token", "secret")
+    ///     .finish_with_body(Some("application/json"), b"{\"name\":\"value\"}")
+    ///     .unwrap();
+    /// assert!(header.auth_param().contains("oauth_body_hash="));
+    /// # }
+    /// ```
+    pub fn finish_with_body(&self, content_type: Option<&str>, body: &[u8]) -> Result<OAuthAuthorizationHeader, HeaderParameterError> {
+        self.finish_with_body_impl(content_type, body, false)
     }
 
     /// Generate `Authorization` header for Twitter.
@@ -466,6 +889,15 @@ impl<'a> OAuthAuthorizationHeaderBuilder<'a> {
     /// # Panics
     /// This function will panic if `url` is not valid for HTTP or HTTPS.
     pub fn finish_for_twitter(&self) -> OAuthAuthorizationHeader {
-        self.finish_impl(true)
+        self.finish_impl(true, None)
+    }
+
+    /// Generate `Authorization` header for Twitter with `body`.
+    /// See `finish_with_body` for details.
+    ///
+    /// # Panics
+    /// This function will panic if `url` is not valid for HTTP or HTTPS.
+    pub fn finish_with_body_for_twitter(&self, content_type: Option<&str>, body: &[u8]) -> Result<OAuthAuthorizationHeader, HeaderParameterError> {
+        self.finish_with_body_impl(content_type, body, true)
     }
 }
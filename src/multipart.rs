@@ -0,0 +1,220 @@
+//! Signing `multipart/form-data` requests.
+//!
+//! [RFC 5849 section 3.4.1.3.1](https://tools.ietf.org/html/rfc5849#section-3.4.1.3.1) includes
+//! only `application/x-www-form-urlencoded` body in the signature,
+//! so the parts of a multipart body must not be passed to `request_parameters`.
+
+use std::borrow::Cow;
+use std::error::Error;
+use std::fmt;
+use super::{OAuthAuthorizationHeader, OAuthAuthorizationHeaderBuilder, RequestBody};
+
+#[derive(Copy, Debug, PartialEq, Eq, Clone, Hash)]
+pub enum MultipartError {
+    /// The boundary is empty, longer than 70 characters or contains characters which need quoting.
+    InvalidBoundary,
+    /// The content type contains control characters such as CR or LF.
+    InvalidContentType
+}
+
+impl Error for MultipartError {
+    fn description(&self) -> &str {
+        match *self {
+            MultipartError::InvalidBoundary => "The boundary must be 1 to 70 characters without any special characters",
+            MultipartError::InvalidContentType => "The content type contains control characters"
+        }
+    }
+}
+
+impl fmt::Display for MultipartError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.description())
+    }
+}
+
+struct Part<'a> {
+    name: Cow<'a, str>,
+    filename: Option<Cow<'a, str>>,
+    content_type: Option<Cow<'a, str>>,
+    data: Cow<'a, [u8]>
+}
+
+/// Description of `multipart/form-data` body.
+pub struct MultipartForm<'a> {
+    boundary: String,
+    parts: Vec<Part<'a>>
+}
+
+impl<'a> MultipartForm<'a> {
+    /// Creates an empty form with a random boundary.
+    pub fn new() -> Self {
+        MultipartForm {
+            boundary: format!("oauthcli-{}", super::nonce()),
+            parts: Vec::new()
+        }
+    }
+
+    /// Creates an empty form with the specified boundary.
+    ///
+    /// Returns `Err` if `boundary` is empty, longer than 70 characters
+    /// or contains characters other than `bcharsnospace` of RFC 2046.
+    pub fn with_boundary<T: Into<String>>(boundary: T) -> Result<Self, MultipartError> {
+        let boundary = boundary.into();
+        let is_valid = boundary.len() > 0 && boundary.len() <= 70 &&
+            boundary.bytes().all(|c| c.is_ascii_alphanumeric() || b"'()+_,-./:=?".contains(&c));
+
+        if !is_valid { return Err(MultipartError::InvalidBoundary) }
+
+        Ok(MultipartForm {
+            boundary: boundary,
+            parts: Vec::new()
+        })
+    }
+
+    /// Adds a text field.
+    pub fn text<N, V>(&mut self, name: N, value: V) -> &mut Self
+        where N: Into<Cow<'a, str>>, V: Into<Cow<'a, str>>
+    {
+        let data = match value.into() {
+            Cow::Borrowed(x) => Cow::Borrowed(x.as_bytes()),
+            Cow::Owned(x) => Cow::Owned(x.into_bytes())
+        };
+
+        self.parts.push(Part {
+            name: name.into(),
+            filename: None,
+            content_type: None,
+            data: data
+        });
+        self
+    }
+
+    /// Adds a file field.
+    ///
+    /// Returns `Err` if `content_type` contains control characters,
+    /// which would break the header of the part.
+    pub fn file<N, F, T, D>(&mut self, name: N, filename: F, content_type: T, data: D) -> Result<&mut Self, MultipartError>
+        where N: Into<Cow<'a, str>>, F: Into<Cow<'a, str>>, T: Into<Cow<'a, str>>, D: Into<Cow<'a, [u8]>>
+    {
+        let content_type = content_type.into();
+        if content_type.bytes().any(|c| c < 0x20 || c == 0x7f) {
+            return Err(MultipartError::InvalidContentType);
+        }
+
+        self.parts.push(Part {
+            name: name.into(),
+            filename: Some(filename.into()),
+            content_type: Some(content_type),
+            data: data.into()
+        });
+        Ok(self)
+    }
+
+    pub fn boundary(&self) -> &str {
+        &self.boundary
+    }
+
+    /// The value of `Content-Type` header.
+    pub fn content_type(&self) -> String {
+        format!("multipart/form-data; boundary={}", self.boundary)
+    }
+
+    /// Generates the request body.
+    pub fn body(&self) -> Vec<u8> {
+        fn push_quoted(buf: &mut Vec<u8>, s: &str) {
+            buf.push(b'"');
+            for &c in s.as_bytes() {
+                match c {
+                    b'"' => buf.extend_from_slice(b"%22"),
+                    b'\r' => buf.extend_from_slice(b"%0D"),
+                    b'\n' => buf.extend_from_slice(b"%0A"),
+                    c => buf.push(c)
+                }
+            }
+            buf.push(b'"');
+        }
+
+        let mut buf = Vec::new();
+
+        for part in self.parts.iter() {
+            buf.extend_from_slice(b"--");
+            buf.extend_from_slice(self.boundary.as_bytes());
+            buf.extend_from_slice(b"\r\nContent-Disposition: form-data; name=");
+            push_quoted(&mut buf, &part.name);
+
+            if let Some(ref x) = part.filename {
+                buf.extend_from_slice(b"; filename=");
+                push_quoted(&mut buf, x);
+            }
+
+            if let Some(ref x) = part.content_type {
+                buf.extend_from_slice(b"\r\nContent-Type: ");
+                buf.extend_from_slice(x.as_bytes());
+            }
+
+            buf.extend_from_slice(b"\r\n\r\n");
+            buf.extend_from_slice(&part.data);
+            buf.extend_from_slice(b"\r\n");
+        }
+
+        buf.extend_from_slice(b"--");
+        buf.extend_from_slice(self.boundary.as_bytes());
+        buf.extend_from_slice(b"--\r\n");
+        buf
+    }
+}
+
+/// `multipart/form-data` request with `Authorization` header.
+#[derive(Debug, Clone)]
+pub struct SignedMultipartRequest {
+    /// The value of `Authorization` header.
+    pub authorization: OAuthAuthorizationHeader,
+    /// The value of `Content-Type` header, which contains the boundary.
+    pub content_type: String,
+    pub body: Vec<u8>
+}
+
+/// Signs the request with `form` as its body.
+/// Only the query of the URL and the protocol parameters are signed,
+/// so `request_parameters` of `builder` are ignored.
+///
+/// # Panics
+/// This function will panic if `url` is not valid for HTTP or HTTPS.
+///
+/// # Example
+/// ```
+/// # extern crate oauthcli;
+/// # extern crate url;
+/// # use oauthcli::*;
+/// # fn main() {
+/// let url = url::Url::parse("https://upload.twitter.com/1.1/media/upload.json").unwrap();
+/// let mut form = multipart::MultipartForm::new();
+/// form.file("media", "image.png", "image/png", &b"\x89PNG"[..]).unwrap();
+///
+/// let req = multipart::sign_for_twitter(
+///     OAuthAuthorizationHeaderBuilder::new("POST", &url, "consumer", "secret", SignatureMethod::HmacSha1)
+///         .token("token", "secret"),
+///     &form);
+/// assert!(req.content_type.starts_with("multipart/form-data; boundary="));
+/// # }
+/// ```
+pub fn sign(builder: &OAuthAuthorizationHeaderBuilder, form: &MultipartForm) -> SignedMultipartRequest {
+    sign_impl(builder, form, false)
+}
+
+/// Signs the request with `form` as its body in the same way as `finish_for_twitter`.
+/// `request_parameters` of `builder` are ignored.
+///
+/// # Panics
+/// This function will panic if `url` is not valid for HTTP or HTTPS.
+pub fn sign_for_twitter(builder: &OAuthAuthorizationHeaderBuilder, form: &MultipartForm) -> SignedMultipartRequest {
+    sign_impl(builder, form, true)
+}
+
+fn sign_impl(builder: &OAuthAuthorizationHeaderBuilder, form: &MultipartForm, for_twitter: bool) -> SignedMultipartRequest {
+    SignedMultipartRequest {
+        authorization: builder.finish_impl(for_twitter, Some(&RequestBody::Multipart)),
+        content_type: form.content_type(),
+        body: form.body()
+    }
+}
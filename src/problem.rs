@@ -0,0 +1,300 @@
+//! [OAuth Problem Reporting](http://wiki.oauth.net/w/page/12238543/ProblemReporting) extension.
+
+use std::borrow::Cow;
+use std::error::Error;
+use std::fmt;
+use url::form_urlencoded;
+use url::percent_encoding::percent_decode;
+use super::{OAuthChallenge, ParseOAuthAuthorizationHeaderError, format_form_parameters, percent_encode};
+
+/// Values of `oauth_problem`.
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub enum OAuthProblem {
+    /// `version_rejected`
+    VersionRejected,
+    /// `parameter_absent`
+    ParameterAbsent,
+    /// `parameter_rejected`
+    ParameterRejected,
+    /// `timestamp_refused`
+    TimestampRefused,
+    /// `nonce_used`
+    NonceUsed,
+    /// `signature_method_rejected`
+    SignatureMethodRejected,
+    /// `signature_invalid`
+    SignatureInvalid,
+    /// `consumer_key_unknown`
+    ConsumerKeyUnknown,
+    /// `consumer_key_rejected`
+    ConsumerKeyRejected,
+    /// `consumer_key_refused`
+    ConsumerKeyRefused,
+    /// `token_used`
+    TokenUsed,
+    /// `token_expired`
+    TokenExpired,
+    /// `token_revoked`
+    TokenRevoked,
+    /// `token_rejected`
+    TokenRejected,
+    /// `additional_authorization_required`
+    AdditionalAuthorizationRequired,
+    /// `permission_unknown`
+    PermissionUnknown,
+    /// `permission_denied`
+    PermissionDenied,
+    /// `user_refused`
+    UserRefused,
+    /// A problem which is not defined in the extension.
+    Other(String)
+}
+
+impl OAuthProblem {
+    pub fn as_str(&self) -> &str {
+        match *self {
+            OAuthProblem::VersionRejected => "version_rejected",
+            OAuthProblem::ParameterAbsent => "parameter_absent",
+            OAuthProblem::ParameterRejected => "parameter_rejected",
+            OAuthProblem::TimestampRefused => "timestamp_refused",
+            OAuthProblem::NonceUsed => "nonce_used",
+            OAuthProblem::SignatureMethodRejected => "signature_method_rejected",
+            OAuthProblem::SignatureInvalid => "signature_invalid",
+            OAuthProblem::ConsumerKeyUnknown => "consumer_key_unknown",
+            OAuthProblem::ConsumerKeyRejected => "consumer_key_rejected",
+            OAuthProblem::ConsumerKeyRefused => "consumer_key_refused",
+            OAuthProblem::TokenUsed => "token_used",
+            OAuthProblem::TokenExpired => "token_expired",
+            OAuthProblem::TokenRevoked => "token_revoked",
+            OAuthProblem::TokenRejected => "token_rejected",
+            OAuthProblem::AdditionalAuthorizationRequired => "additional_authorization_required",
+            OAuthProblem::PermissionUnknown => "permission_unknown",
+            OAuthProblem::PermissionDenied => "permission_denied",
+            OAuthProblem::UserRefused => "user_refused",
+            OAuthProblem::Other(ref x) => x
+        }
+    }
+}
+
+impl<'a> From<&'a str> for OAuthProblem {
+    fn from(s: &'a str) -> OAuthProblem {
+        match s {
+            "version_rejected" => OAuthProblem::VersionRejected,
+            "parameter_absent" => OAuthProblem::ParameterAbsent,
+            "parameter_rejected" => OAuthProblem::ParameterRejected,
+            "timestamp_refused" => OAuthProblem::TimestampRefused,
+            "nonce_used" => OAuthProblem::NonceUsed,
+            "signature_method_rejected" => OAuthProblem::SignatureMethodRejected,
+            "signature_invalid" => OAuthProblem::SignatureInvalid,
+            "consumer_key_unknown" => OAuthProblem::ConsumerKeyUnknown,
+            "consumer_key_rejected" => OAuthProblem::ConsumerKeyRejected,
+            "consumer_key_refused" => OAuthProblem::ConsumerKeyRefused,
+            "token_used" => OAuthProblem::TokenUsed,
+            "token_expired" => OAuthProblem::TokenExpired,
+            "token_revoked" => OAuthProblem::TokenRevoked,
+            "token_rejected" => OAuthProblem::TokenRejected,
+            "additional_authorization_required" => OAuthProblem::AdditionalAuthorizationRequired,
+            "permission_unknown" => OAuthProblem::PermissionUnknown,
+            "permission_denied" => OAuthProblem::PermissionDenied,
+            "user_refused" => OAuthProblem::UserRefused,
+            x => OAuthProblem::Other(x.to_owned())
+        }
+    }
+}
+
+impl fmt::Display for OAuthProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Copy, Debug, PartialEq, Eq, Clone, Hash)]
+pub enum ParseOAuthProblemError {
+    /// The `WWW-Authenticate` header is not valid.
+    HeaderError(ParseOAuthAuthorizationHeaderError),
+    /// `oauth_problem` is not found.
+    ProblemAbsent,
+    /// The value of the parameter is malformed.
+    InvalidParameter(&'static str)
+}
+
+impl Error for ParseOAuthProblemError {
+    fn description(&self) -> &str {
+        match *self {
+            ParseOAuthProblemError::HeaderError(ref e) => e.description(),
+            ParseOAuthProblemError::ProblemAbsent => "`oauth_problem` is not found",
+            ParseOAuthProblemError::InvalidParameter(_) => "The value of the parameter is malformed"
+        }
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            ParseOAuthProblemError::HeaderError(ref e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+impl fmt::Display for ParseOAuthProblemError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseOAuthProblemError::InvalidParameter(x) => write!(f, "The value of `{}` is malformed", x),
+            _ => f.write_str(self.description())
+        }
+    }
+}
+
+impl From<ParseOAuthAuthorizationHeaderError> for ParseOAuthProblemError {
+    fn from(e: ParseOAuthAuthorizationHeaderError) -> ParseOAuthProblemError {
+        ParseOAuthProblemError::HeaderError(e)
+    }
+}
+
+/// A problem reported by the Service Provider.
+///
+/// # Example
+/// ```
+/// # use oauthcli::{OAuthProblem, OAuthProblemReport};
+/// let report = OAuthProblemReport::from_www_authenticate(
+///     "OAuth realm=\"Photos\", oauth_problem=\"timestamp_refused\", \
+///      oauth_acceptable_timestamps=\"137131200-137131300\"").unwrap();
+/// assert_eq!(report.problem, OAuthProblem::TimestampRefused);
+/// assert_eq!(report.acceptable_timestamps, Some((137131200, 137131300)));
+/// ```
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct OAuthProblemReport {
+    /// `oauth_problem`
+    pub problem: OAuthProblem,
+    /// `oauth_acceptable_versions` as an inclusive range.
+    pub acceptable_versions: Option<(String, String)>,
+    /// `oauth_acceptable_timestamps` as an inclusive range.
+    pub acceptable_timestamps: Option<(u64, u64)>,
+    /// `oauth_parameters_absent`
+    pub parameters_absent: Vec<String>,
+    /// `oauth_parameters_rejected`
+    pub parameters_rejected: Vec<String>,
+    /// `oauth_problem_advice`
+    pub problem_advice: Option<String>
+}
+
+impl OAuthProblemReport {
+    pub fn new(problem: OAuthProblem) -> OAuthProblemReport {
+        OAuthProblemReport {
+            problem: problem,
+            acceptable_versions: None,
+            acceptable_timestamps: None,
+            parameters_absent: Vec::new(),
+            parameters_rejected: Vec::new(),
+            problem_advice: None
+        }
+    }
+
+    /// Parses the value of `WWW-Authenticate` header.
+    pub fn from_www_authenticate(s: &str) -> Result<OAuthProblemReport, ParseOAuthProblemError> {
+        let challenge: OAuthChallenge = try!(s.parse());
+        challenge.problem_report_owned().ok_or(ParseOAuthProblemError::ProblemAbsent)
+    }
+
+    /// Parses the response body encoded as `application/x-www-form-urlencoded`.
+    pub fn from_body(s: &str) -> Result<OAuthProblemReport, ParseOAuthProblemError> {
+        OAuthProblemReport::from_parameters(form_urlencoded::parse(s.as_bytes()))
+    }
+
+    /// Collects the Problem Reporting parameters from decoded key-value pairs.
+    /// Unknown parameters are ignored.
+    pub fn from_parameters<'a, P>(params: P) -> Result<OAuthProblemReport, ParseOAuthProblemError>
+        where P: IntoIterator<Item=(Cow<'a, str>, Cow<'a, str>)>
+    {
+        fn parse_range<'b>(s: &'b str, name: &'static str) -> Result<(&'b str, &'b str), ParseOAuthProblemError> {
+            let mut i = s.splitn(2, '-');
+            match (i.next(), i.next()) {
+                (Some(a), Some(b)) if a.len() > 0 && b.len() > 0 => Ok((a, b)),
+                _ => Err(ParseOAuthProblemError::InvalidParameter(name))
+            }
+        }
+
+        fn parse_list(s: &str, name: &'static str) -> Result<Vec<String>, ParseOAuthProblemError> {
+            let mut result = Vec::new();
+            for x in s.split('&').filter(|x| x.len() > 0) {
+                match percent_decode(x.as_bytes()).decode_utf8() {
+                    Ok(x) => result.push(x.into_owned()),
+                    Err(_) => return Err(ParseOAuthProblemError::InvalidParameter(name))
+                }
+            }
+            Ok(result)
+        }
+
+        let mut problem = None;
+        let mut report = OAuthProblemReport::new(OAuthProblem::Other(String::new()));
+
+        for (k, v) in params {
+            match &k[..] {
+                "oauth_problem" => problem = Some(OAuthProblem::from(&v[..])),
+                "oauth_acceptable_versions" => {
+                    let (a, b) = try!(parse_range(&v, "oauth_acceptable_versions"));
+                    report.acceptable_versions = Some((a.to_owned(), b.to_owned()));
+                },
+                "oauth_acceptable_timestamps" => {
+                    const NAME: &'static str = "oauth_acceptable_timestamps";
+                    let (a, b) = try!(parse_range(&v, NAME));
+                    match (a.parse(), b.parse()) {
+                        (Ok(a), Ok(b)) => report.acceptable_timestamps = Some((a, b)),
+                        _ => return Err(ParseOAuthProblemError::InvalidParameter(NAME))
+                    }
+                },
+                "oauth_parameters_absent" =>
+                    report.parameters_absent = try!(parse_list(&v, "oauth_parameters_absent")),
+                "oauth_parameters_rejected" =>
+                    report.parameters_rejected = try!(parse_list(&v, "oauth_parameters_rejected")),
+                "oauth_problem_advice" => report.problem_advice = Some(v.into_owned()),
+                _ => ()
+            }
+        }
+
+        match problem {
+            Some(x) => {
+                report.problem = x;
+                Ok(report)
+            },
+            None => Err(ParseOAuthProblemError::ProblemAbsent)
+        }
+    }
+
+    /// Returns the Problem Reporting parameters as key-value pairs, which are not encoded yet.
+    pub fn to_parameters(&self) -> Vec<(&'static str, String)> {
+        fn join(names: &[String]) -> String {
+            let mut result = String::new();
+            for x in names {
+                if result.len() > 0 { result.push('&'); }
+                result.extend(percent_encode(x));
+            }
+            result
+        }
+
+        let mut p = Vec::with_capacity(6);
+
+        p.push(("oauth_problem", self.problem.as_str().to_owned()));
+        if let Some((ref a, ref b)) = self.acceptable_versions {
+            p.push(("oauth_acceptable_versions", format!("{}-{}", a, b)));
+        }
+        if let Some((a, b)) = self.acceptable_timestamps {
+            p.push(("oauth_acceptable_timestamps", format!("{}-{}", a, b)));
+        }
+        if self.parameters_absent.len() > 0 {
+            p.push(("oauth_parameters_absent", join(&self.parameters_absent)));
+        }
+        if self.parameters_rejected.len() > 0 {
+            p.push(("oauth_parameters_rejected", join(&self.parameters_rejected)));
+        }
+        if let Some(ref x) = self.problem_advice {
+            p.push(("oauth_problem_advice", x.clone()));
+        }
+
+        p
+    }
+
+    /// Generates the response body encoded as `application/x-www-form-urlencoded`.
+    pub fn to_body(&self) -> String {
+        format_form_parameters(self.to_parameters().iter().map(|&(k, ref v)| (k, &v[..])))
+    }
+}
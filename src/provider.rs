@@ -0,0 +1,612 @@
+//! Framework for Service Providers which issue credentials with the endpoints of
+//! [RFC 5849 section 2](https://tools.ietf.org/html/rfc5849#section-2).
+//!
+//! `Provider` verifies each signed request with `SignedRequest`, and keeps the credentials in a store
+//! implementing `ConsumerStore`, `RequestTokenStore`, `AccessTokenStore` and `NonceStore`.
+//! The HTTP server passes the request to the handler of the endpoint, and responds with
+//! `OAuthTokenResponse::to_body` or the `OAuthProblemReport` of the error.
+//!
+//! # Example
+//! ```
+//! # extern crate oauthcli;
+//! # extern crate url;
+//! # use oauthcli::*;
+//! # use oauthcli::flow::*;
+//! # use oauthcli::provider::*;
+//! # fn main() {
+//! let store = MemoryProviderStore::new();
+//! store.insert_consumer(ConsumerRecord {
+//!     credentials: ClientCredentials::new("dpf43f3p2l4k3l03", "kd94hf93k423kf44"),
+//!     callback_prefix: None
+//! });
+//! let provider = Provider::new(store);
+//! let now = 1500000000;
+//!
+//! // POST Temporary Credential Request
+//! let url = url::Url::parse("https://photos.example.net/initiate").unwrap();
+//! let header = RequestToken::request(
+//!     &url, "dpf43f3p2l4k3l03", "kd94hf93k423kf44", SignatureMethod::HmacSha1,
+//!     "http://printer.example.com/ready").timestamp(now).finish();
+//! let res = provider.request_token("POST", &url, &header, &[], now).unwrap();
+//! let request_token = RequestToken::from_response(&res).unwrap();
+//!
+//! // The Resource Owner signs in and approves the request token
+//! let authorization = provider.authorize(request_token.token(), "jane", now).unwrap();
+//! let callback = OAuthCallback::from_url(&authorization.redirect_url().unwrap()).unwrap();
+//! let authorized = request_token.authorize(&callback).unwrap();
+//!
+//! // POST Token Request
+//! let url = url::Url::parse("https://photos.example.net/token").unwrap();
+//! let header = authorized.access_token_request(
+//!     &url, "dpf43f3p2l4k3l03", "kd94hf93k423kf44", SignatureMethod::HmacSha1).timestamp(now).finish();
+//! let res = provider.access_token("POST", &url, &header, &[], now).unwrap();
+//! let access_token = authorized.into_access_token(&res);
+//!
+//! // Protected resources
+//! let url = url::Url::parse("http://photos.example.net/photos?file=vacation.jpg&size=original").unwrap();
+//! let header = access_token.request(
+//!     "GET", &url, "dpf43f3p2l4k3l03", "kd94hf93k423kf44", SignatureMethod::HmacSha1).timestamp(now).finish();
+//! assert_eq!(provider.authenticate("GET", &url, &header, &[], now).unwrap().user, "jane");
+//! # }
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::convert::Infallible;
+use std::error::Error;
+use std::fmt;
+use std::sync::Mutex;
+use url::Url;
+use super::{ClientCredentials, OAuthAuthorizationHeader, OAuthProblem, OAuthProblemReport, OAuthTokenResponse};
+use super::{SignedRequest, TokenCredentials, VerifyError, VerifyOptions};
+use super::crypto;
+use super::signer::{HmacSha1Signer, PlaintextSigner, SignerRegistry};
+use super::store::lock;
+
+const TOKEN_LENGTH: usize = 32;
+const SECRET_LENGTH: usize = 40;
+const VERIFIER_LENGTH: usize = 24;
+
+/// A consumer registered to the Service Provider.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ConsumerRecord {
+    pub credentials: ClientCredentials,
+    /// If set, `oauth_callback` other than `oob` must have the same scheme, host and port as this URL,
+    /// and its path must be the path of this URL or below it.
+    pub callback_prefix: Option<String>
+}
+
+/// Temporary credentials issued by the Temporary Credential Request endpoint.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct RequestTokenRecord {
+    /// `oauth_consumer_key` of the consumer which requested the token.
+    pub consumer_key: String,
+    pub credentials: TokenCredentials,
+    /// `oauth_callback`, which may be `oob`.
+    pub callback: String,
+    /// The Resource Owner who approved the token.
+    pub user: Option<String>,
+    /// `oauth_verifier`, which is set when the Resource Owner approves the token.
+    pub verifier: Option<String>,
+    /// Seconds since the Unix epoch.
+    pub expires_at: u64
+}
+
+/// Token credentials issued by the Token Request endpoint.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct AccessTokenRecord {
+    /// `oauth_consumer_key` of the consumer which requested the token.
+    pub consumer_key: String,
+    pub credentials: TokenCredentials,
+    /// The Resource Owner who approved the token.
+    pub user: String
+}
+
+/// Looks up the registered consumers.
+pub trait ConsumerStore {
+    type Error;
+
+    /// Finds the consumer by `oauth_consumer_key`.
+    fn consumer(&self, key: &str) -> Result<Option<ConsumerRecord>, Self::Error>;
+}
+
+/// Keeps the request tokens until they are exchanged or expire.
+///
+/// The times are in seconds since the Unix epoch.
+pub trait RequestTokenStore {
+    type Error;
+
+    fn insert_request_token(&self, record: &RequestTokenRecord) -> Result<(), Self::Error>;
+
+    /// Finds the request token unless it has expired at `now`.
+    fn request_token(&self, token: &str, now: u64) -> Result<Option<RequestTokenRecord>, Self::Error>;
+
+    /// Sets `user` and `verifier` of the request token only if it has not been authorized yet.
+    /// Returns `false` if the token is not found, has expired at `now` or has already been authorized.
+    ///
+    /// The check and the update must be atomic, so that concurrent approvals issue only one verifier.
+    fn authorize_request_token(&self, token: &str, user: &str, verifier: &str, now: u64) -> Result<bool, Self::Error>;
+
+    /// Removes and returns the request token unless it has expired at `now`.
+    /// The token is returned only once.
+    fn take_request_token(&self, token: &str, now: u64) -> Result<Option<RequestTokenRecord>, Self::Error>;
+
+    /// Removes the request tokens which have expired at `now`.
+    fn remove_expired_request_tokens(&self, now: u64) -> Result<(), Self::Error>;
+}
+
+/// Keeps the access tokens until they are revoked.
+pub trait AccessTokenStore {
+    type Error;
+
+    fn insert_access_token(&self, record: &AccessTokenRecord) -> Result<(), Self::Error>;
+
+    fn access_token(&self, token: &str) -> Result<Option<AccessTokenRecord>, Self::Error>;
+
+    /// Returns `false` if the token is not found.
+    fn revoke_access_token(&self, token: &str) -> Result<bool, Self::Error>;
+}
+
+/// Remembers the nonces to reject replayed requests
+/// ([RFC 5849 section 3.3](https://tools.ietf.org/html/rfc5849#section-3.3)).
+pub trait NonceStore {
+    type Error;
+
+    /// Records the nonce, and returns `false` if the same combination has already been recorded.
+    fn use_nonce(&self, consumer_key: &str, token: Option<&str>, timestamp: u64, nonce: &str) -> Result<bool, Self::Error>;
+
+    /// Forgets the nonces whose timestamps are older than `timestamp`.
+    fn remove_nonces_before(&self, timestamp: u64) -> Result<(), Self::Error>;
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ProviderError<E> {
+    /// The request is rejected. Respond with the report.
+    Problem(OAuthProblemReport),
+    /// The store failed.
+    Store(E)
+}
+
+impl<E> ProviderError<E> {
+    /// The status code of the response
+    /// ([RFC 5849 section 3.2](https://tools.ietf.org/html/rfc5849#section-3.2)).
+    pub fn status_code(&self) -> u16 {
+        match *self {
+            ProviderError::Problem(ref x) => match x.problem {
+                OAuthProblem::VersionRejected | OAuthProblem::ParameterAbsent |
+                OAuthProblem::ParameterRejected | OAuthProblem::SignatureMethodRejected => 400,
+                _ => 401
+            },
+            ProviderError::Store(_) => 500
+        }
+    }
+}
+
+impl<E: Error> Error for ProviderError<E> {
+    fn description(&self) -> &str {
+        match *self {
+            ProviderError::Problem(_) => "The request is rejected",
+            ProviderError::Store(ref e) => e.description()
+        }
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            ProviderError::Store(ref e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+impl<E: fmt::Display> fmt::Display for ProviderError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ProviderError::Problem(ref x) => write!(f, "The request is rejected: {}", x.problem.as_str()),
+            ProviderError::Store(ref e) => fmt::Display::fmt(e, f)
+        }
+    }
+}
+
+impl<E> From<OAuthProblemReport> for ProviderError<E> {
+    fn from(x: OAuthProblemReport) -> ProviderError<E> {
+        ProviderError::Problem(x)
+    }
+}
+
+fn problem<E>(x: OAuthProblem) -> ProviderError<E> {
+    ProviderError::Problem(OAuthProblemReport::new(x))
+}
+
+fn parameter_absent<E>(name: &str) -> ProviderError<E> {
+    let mut report = OAuthProblemReport::new(OAuthProblem::ParameterAbsent);
+    report.parameters_absent.push(name.to_owned());
+    ProviderError::Problem(report)
+}
+
+fn parameter_rejected<E>(name: &str) -> ProviderError<E> {
+    let mut report = OAuthProblemReport::new(OAuthProblem::ParameterRejected);
+    report.parameters_rejected.push(name.to_owned());
+    ProviderError::Problem(report)
+}
+
+fn verify_error<E>(e: VerifyError) -> ProviderError<E> {
+    match e {
+        VerifyError::ParameterAbsent(x) => parameter_absent(x),
+        _ => problem(e.to_problem())
+    }
+}
+
+/// Generates a string with the random number generator of the OS.
+///
+/// # Panics
+/// This function will panic if the random number generator of the OS is not available.
+fn random_string(len: usize) -> String {
+    use rand::{OsRng, Rng};
+
+    let mut rng = OsRng::new().expect("The random number generator of the OS is not available");
+    rng.gen_ascii_chars().take(len).collect()
+}
+
+fn new_credentials() -> TokenCredentials {
+    TokenCredentials::new(random_string(TOKEN_LENGTH), random_string(SECRET_LENGTH))
+}
+
+fn token_response(credentials: &TokenCredentials, callback_confirmed: Option<bool>) -> OAuthTokenResponse {
+    OAuthTokenResponse {
+        token: credentials.token.clone(),
+        token_secret: credentials.secret.clone(),
+        callback_confirmed: callback_confirmed,
+        session_handle: None,
+        expires_in: None,
+        authorization_expires_in: None,
+        other_parameters: Vec::new()
+    }
+}
+
+pub(crate) fn is_valid_callback(callback: &str, prefix: Option<&str>) -> bool {
+    if callback == "oob" { return true; }
+
+    let callback = match Url::parse(callback) {
+        Ok(ref x) if x.scheme() == "http" || x.scheme() == "https" => x.clone(),
+        _ => return false
+    };
+    let prefix = match prefix {
+        Some(x) => match Url::parse(x) {
+            Ok(x) => x,
+            Err(_) => return false
+        },
+        None => return true
+    };
+
+    // Compare the parsed URLs, because `https://client.example.com.evil.example/` starts with the string
+    let (path, prefix_path) = (callback.path(), prefix.path());
+    callback.scheme() == prefix.scheme()
+        && callback.host() == prefix.host()
+        && callback.port_or_known_default() == prefix.port_or_known_default()
+        && path.starts_with(prefix_path)
+        && (prefix_path.ends_with('/') || path.len() == prefix_path.len() || path[prefix_path.len()..].starts_with('/'))
+}
+
+/// The approval of the Resource Owner, returned by `Provider::authorize`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Authorization {
+    /// `oauth_token`
+    pub token: String,
+    /// `oauth_verifier`
+    pub verifier: String,
+    /// `oauth_callback`, which may be `oob`.
+    pub callback: String
+}
+
+impl Authorization {
+    /// Returns the callback URI with `oauth_token` and `oauth_verifier` to redirect the user agent to.
+    /// If the callback is `oob`, returns `None`, and the verifier should be displayed to the Resource Owner.
+    pub fn redirect_url(&self) -> Option<Url> {
+        if self.callback == "oob" { return None; }
+
+        Url::parse(&self.callback).ok().map(|mut url| {
+            url.query_pairs_mut()
+                .append_pair("oauth_token", &self.token)
+                .append_pair("oauth_verifier", &self.verifier);
+            url
+        })
+    }
+}
+
+/// Handlers of the endpoints.
+///
+/// `request_parameters` of each handler are the parameters of the form-encoded body
+/// decoded by `decode_form_body`. The times are in seconds since the Unix epoch.
+#[derive(Debug)]
+pub struct Provider<S> {
+    store: S,
+    signers: SignerRegistry,
+    request_token_ttl: u64,
+    timestamp_window: u64
+}
+
+impl<S> Provider<S> {
+    /// Creates a provider which accepts HMAC-SHA1 and PLAINTEXT, whose request tokens expire in 10 minutes,
+    /// and which accepts `oauth_timestamp` within 5 minutes.
+    pub fn new(store: S) -> Provider<S> {
+        let mut signers = SignerRegistry::empty();
+        signers
+            .register("HMAC-SHA1", HmacSha1Signer)
+            .register("PLAINTEXT", PlaintextSigner);
+
+        Provider { store: store, signers: signers, request_token_ttl: 600, timestamp_window: 300 }
+    }
+
+    /// Replaces the accepted signature methods with the ones in `signers`.
+    /// PLAINTEXT is rejected unless the request URL is HTTPS, even if it is registered.
+    pub fn signers(&mut self, signers: SignerRegistry) -> &mut Self {
+        self.signers = signers;
+        self
+    }
+
+    /// Sets the seconds until the request tokens expire.
+    pub fn request_token_ttl(&mut self, seconds: u64) -> &mut Self {
+        self.request_token_ttl = seconds;
+        self
+    }
+
+    /// Sets the seconds of the difference between `oauth_timestamp` and the clock of the provider.
+    pub fn timestamp_window(&mut self, seconds: u64) -> &mut Self {
+        self.timestamp_window = seconds;
+        self
+    }
+
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+}
+
+impl<S, E> Provider<S>
+    where S: ConsumerStore<Error = E> + RequestTokenStore<Error = E> + AccessTokenStore<Error = E> + NonceStore<Error = E>
+{
+    /// Parses the header and finds the consumer.
+    fn consumer_request(&self, header: &OAuthAuthorizationHeader) -> Result<(SignedRequest, ConsumerRecord), ProviderError<E>> {
+        let req = try!(SignedRequest::from_header_with_registry(header, &self.signers).map_err(verify_error));
+        let consumer = try!(try!(self.store.consumer(req.consumer_key()).map_err(ProviderError::Store))
+            .ok_or_else(|| problem(OAuthProblem::ConsumerKeyUnknown)));
+        Ok((req, consumer))
+    }
+
+    /// Verifies the signature, and then checks the timestamp and the nonce.
+    fn check(&self, req: &SignedRequest, consumer: &ConsumerRecord, token_secret: Option<&str>,
+        method: &str, url: &Url, request_parameters: &[(Vec<u8>, Vec<u8>)], now: u64) -> Result<(), ProviderError<E>>
+    {
+        // PLAINTEXT sends the secrets as they are (RFC 5849 section 3.4.4)
+        if req.signature_method_name() == "PLAINTEXT" && url.scheme() != "https" {
+            return Err(problem(OAuthProblem::SignatureMethodRejected));
+        }
+
+        let params = request_parameters.iter().map(|&(ref k, ref v)| (&k[..], &v[..]));
+        try!(req.verify_with(VerifyOptions::new().registry(&self.signers), method, url, params,
+            consumer.credentials.secret.expose(), token_secret).map_err(verify_error));
+
+        // PLAINTEXT without oauth_timestamp relies on TLS
+        if let Some(timestamp) = req.timestamp() {
+            let from = now.saturating_sub(self.timestamp_window);
+            let to = now.saturating_add(self.timestamp_window);
+            if timestamp < from || timestamp > to {
+                let mut report = OAuthProblemReport::new(OAuthProblem::TimestampRefused);
+                report.acceptable_timestamps = Some((from, to));
+                return Err(ProviderError::Problem(report));
+            }
+
+            let nonce = req.nonce().unwrap_or("");
+            if !try!(self.store.use_nonce(req.consumer_key(), req.token(), timestamp, nonce).map_err(ProviderError::Store)) {
+                return Err(problem(OAuthProblem::NonceUsed));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Handles Temporary Credential Request, which requires `oauth_callback`.
+    pub fn request_token(&self, method: &str, url: &Url, header: &OAuthAuthorizationHeader,
+        request_parameters: &[(Vec<u8>, Vec<u8>)], now: u64) -> Result<OAuthTokenResponse, ProviderError<E>>
+    {
+        let (req, consumer) = try!(self.consumer_request(header));
+        try!(self.check(&req, &consumer, None, method, url, request_parameters, now));
+
+        let callback = try!(req.parameter("oauth_callback").ok_or_else(|| parameter_absent("oauth_callback")));
+        if !is_valid_callback(callback, consumer.callback_prefix.as_ref().map(|x| &x[..])) {
+            return Err(parameter_rejected("oauth_callback"));
+        }
+
+        let record = RequestTokenRecord {
+            consumer_key: consumer.credentials.key,
+            credentials: new_credentials(),
+            callback: callback.to_owned(),
+            user: None,
+            verifier: None,
+            expires_at: now.saturating_add(self.request_token_ttl)
+        };
+        try!(self.store.insert_request_token(&record).map_err(ProviderError::Store));
+
+        Ok(token_response(&record.credentials, Some(true)))
+    }
+
+    /// Issues `oauth_verifier` after the Resource Owner `user` approves `token`.
+    /// Call this from the Resource Owner Authorization endpoint, which authenticates the user.
+    pub fn authorize(&self, token: &str, user: &str, now: u64) -> Result<Authorization, ProviderError<E>> {
+        let record = try!(try!(self.store.request_token(token, now).map_err(ProviderError::Store))
+            .ok_or_else(|| problem(OAuthProblem::TokenRejected)));
+        if record.verifier.is_some() {
+            return Err(problem(OAuthProblem::TokenUsed));
+        }
+
+        let verifier = random_string(VERIFIER_LENGTH);
+        if !try!(self.store.authorize_request_token(token, user, &verifier, now).map_err(ProviderError::Store)) {
+            // Approved concurrently
+            return Err(problem(OAuthProblem::TokenUsed));
+        }
+
+        Ok(Authorization { token: record.credentials.token, verifier: verifier, callback: record.callback })
+    }
+
+    /// Discards `token` after the Resource Owner denies it.
+    /// Returns the callback URI with `denied`, or `None` if the callback is `oob`.
+    pub fn deny(&self, token: &str, now: u64) -> Result<Option<Url>, ProviderError<E>> {
+        let record = try!(try!(self.store.take_request_token(token, now).map_err(ProviderError::Store))
+            .ok_or_else(|| problem(OAuthProblem::TokenRejected)));
+        if record.callback == "oob" { return Ok(None); }
+
+        Ok(Url::parse(&record.callback).ok().map(|mut url| {
+            url.query_pairs_mut().append_pair("denied", token);
+            url
+        }))
+    }
+
+    /// Handles Token Request, which exchanges the approved request token for an access token.
+    pub fn access_token(&self, method: &str, url: &Url, header: &OAuthAuthorizationHeader,
+        request_parameters: &[(Vec<u8>, Vec<u8>)], now: u64) -> Result<OAuthTokenResponse, ProviderError<E>>
+    {
+        let (req, consumer) = try!(self.consumer_request(header));
+        let token = try!(req.token().ok_or_else(|| parameter_absent("oauth_token")));
+        let record = match try!(self.store.request_token(token, now).map_err(ProviderError::Store)) {
+            Some(ref x) if x.consumer_key == consumer.credentials.key => x.clone(),
+            _ => return Err(problem(OAuthProblem::TokenRejected))
+        };
+        try!(self.check(&req, &consumer, Some(record.credentials.secret.expose()), method, url, request_parameters, now));
+
+        let verifier = try!(req.parameter("oauth_verifier").ok_or_else(|| parameter_absent("oauth_verifier")));
+        let user = match (record.user, record.verifier) {
+            (Some(user), Some(ref x)) if crypto::constant_time_eq(x.as_bytes(), verifier.as_bytes()) => user,
+            (Some(_), Some(_)) => return Err(parameter_rejected("oauth_verifier")),
+            _ => return Err(problem(OAuthProblem::PermissionUnknown))
+        };
+
+        if try!(self.store.take_request_token(token, now).map_err(ProviderError::Store)).is_none() {
+            return Err(problem(OAuthProblem::TokenUsed));
+        }
+
+        let record = AccessTokenRecord {
+            consumer_key: consumer.credentials.key,
+            credentials: new_credentials(),
+            user: user
+        };
+        try!(self.store.insert_access_token(&record).map_err(ProviderError::Store));
+
+        Ok(token_response(&record.credentials, None))
+    }
+
+    /// Verifies a request to the protected resources, and returns the access token.
+    pub fn authenticate(&self, method: &str, url: &Url, header: &OAuthAuthorizationHeader,
+        request_parameters: &[(Vec<u8>, Vec<u8>)], now: u64) -> Result<AccessTokenRecord, ProviderError<E>>
+    {
+        let (req, consumer) = try!(self.consumer_request(header));
+        let token = try!(req.token().ok_or_else(|| parameter_absent("oauth_token")));
+        let record = match try!(self.store.access_token(token).map_err(ProviderError::Store)) {
+            Some(ref x) if x.consumer_key == consumer.credentials.key => x.clone(),
+            _ => return Err(problem(OAuthProblem::TokenRejected))
+        };
+        try!(self.check(&req, &consumer, Some(record.credentials.secret.expose()), method, url, request_parameters, now));
+
+        Ok(record)
+    }
+
+    /// Removes the expired request tokens and the nonces which can no longer be replayed.
+    pub fn remove_expired(&self, now: u64) -> Result<(), E> {
+        try!(self.store.remove_expired_request_tokens(now));
+        self.store.remove_nonces_before(now.saturating_sub(self.timestamp_window))
+    }
+}
+
+/// The stores in memory, which are shared by the threads of a process.
+#[derive(Debug, Default)]
+pub struct MemoryProviderStore {
+    consumers: Mutex<HashMap<String, ConsumerRecord>>,
+    request_tokens: Mutex<HashMap<String, RequestTokenRecord>>,
+    access_tokens: Mutex<HashMap<String, AccessTokenRecord>>,
+    nonces: Mutex<HashSet<(String, Option<String>, u64, String)>>
+}
+
+impl MemoryProviderStore {
+    pub fn new() -> MemoryProviderStore {
+        MemoryProviderStore::default()
+    }
+
+    /// Registers the consumer.
+    pub fn insert_consumer(&self, record: ConsumerRecord) {
+        lock(&self.consumers).insert(record.credentials.key.clone(), record);
+    }
+
+    /// Unregisters the consumer. Returns `false` if the consumer is not found.
+    pub fn remove_consumer(&self, key: &str) -> bool {
+        lock(&self.consumers).remove(key).is_some()
+    }
+}
+
+impl ConsumerStore for MemoryProviderStore {
+    type Error = Infallible;
+
+    fn consumer(&self, key: &str) -> Result<Option<ConsumerRecord>, Infallible> {
+        Ok(lock(&self.consumers).get(key).cloned())
+    }
+}
+
+impl RequestTokenStore for MemoryProviderStore {
+    type Error = Infallible;
+
+    fn insert_request_token(&self, record: &RequestTokenRecord) -> Result<(), Infallible> {
+        lock(&self.request_tokens).insert(record.credentials.token.clone(), record.clone());
+        Ok(())
+    }
+
+    fn request_token(&self, token: &str, now: u64) -> Result<Option<RequestTokenRecord>, Infallible> {
+        Ok(lock(&self.request_tokens).get(token).filter(|x| now < x.expires_at).cloned())
+    }
+
+    fn authorize_request_token(&self, token: &str, user: &str, verifier: &str, now: u64) -> Result<bool, Infallible> {
+        match lock(&self.request_tokens).get_mut(token) {
+            Some(ref mut x) if now < x.expires_at && x.verifier.is_none() => {
+                x.user = Some(user.to_owned());
+                x.verifier = Some(verifier.to_owned());
+                Ok(true)
+            },
+            _ => Ok(false)
+        }
+    }
+
+    fn take_request_token(&self, token: &str, now: u64) -> Result<Option<RequestTokenRecord>, Infallible> {
+        Ok(lock(&self.request_tokens).remove(token).filter(|x| now < x.expires_at))
+    }
+
+    fn remove_expired_request_tokens(&self, now: u64) -> Result<(), Infallible> {
+        lock(&self.request_tokens).retain(|_, x| now < x.expires_at);
+        Ok(())
+    }
+}
+
+impl AccessTokenStore for MemoryProviderStore {
+    type Error = Infallible;
+
+    fn insert_access_token(&self, record: &AccessTokenRecord) -> Result<(), Infallible> {
+        lock(&self.access_tokens).insert(record.credentials.token.clone(), record.clone());
+        Ok(())
+    }
+
+    fn access_token(&self, token: &str) -> Result<Option<AccessTokenRecord>, Infallible> {
+        Ok(lock(&self.access_tokens).get(token).cloned())
+    }
+
+    fn revoke_access_token(&self, token: &str) -> Result<bool, Infallible> {
+        Ok(lock(&self.access_tokens).remove(token).is_some())
+    }
+}
+
+impl NonceStore for MemoryProviderStore {
+    type Error = Infallible;
+
+    fn use_nonce(&self, consumer_key: &str, token: Option<&str>, timestamp: u64, nonce: &str) -> Result<bool, Infallible> {
+        let key = (consumer_key.to_owned(), token.map(|x| x.to_owned()), timestamp, nonce.to_owned());
+        Ok(lock(&self.nonces).insert(key))
+    }
+
+    fn remove_nonces_before(&self, timestamp: u64) -> Result<(), Infallible> {
+        lock(&self.nonces).retain(|&(_, _, x, _)| x >= timestamp);
+        Ok(())
+    }
+}
@@ -0,0 +1,170 @@
+//! Types which hold secrets.
+//!
+//! They are redacted in `Debug` and `Display`, and the owned values are zeroized on drop.
+
+use alloc::borrow::Cow;
+use alloc::fmt;
+use alloc::string::String;
+use alloc::vec::Vec;
+#[cfg(feature = "std")] use std::hash::{Hash, Hasher};
+#[cfg(not(feature = "std"))] use core::hash::{Hash, Hasher};
+use zeroize::Zeroize;
+use super::crypto;
+
+const REDACTED: &'static str = "[REDACTED]";
+
+macro_rules! secret_string {
+    ($(#[$attr:meta])* $name:ident) => {
+        $(#[$attr])*
+        pub struct $name<'a>(Cow<'a, str>);
+
+        impl<'a> $name<'a> {
+            pub fn new<T: Into<Cow<'a, str>>>(secret: T) -> $name<'a> {
+                $name(secret.into())
+            }
+
+            /// Returns the secret value.
+            pub fn expose(&self) -> &str {
+                &self.0
+            }
+
+            /// Copies the secret to make it `'static`.
+            pub fn into_owned(self) -> $name<'static> {
+                $name(Cow::Owned(self.expose().into()))
+            }
+        }
+
+        impl<'a> Drop for $name<'a> {
+            fn drop(&mut self) {
+                if let Cow::Owned(ref mut x) = self.0 {
+                    x.zeroize();
+                }
+            }
+        }
+
+        impl<'a> Clone for $name<'a> {
+            fn clone(&self) -> $name<'a> {
+                $name(self.0.clone())
+            }
+        }
+
+        /// Compares in constant time.
+        impl<'a, 'b> PartialEq<$name<'b>> for $name<'a> {
+            fn eq(&self, other: &$name<'b>) -> bool {
+                crypto::constant_time_eq(self.expose().as_bytes(), other.expose().as_bytes())
+            }
+        }
+
+        impl<'a> Eq for $name<'a> {}
+
+        impl<'a> Hash for $name<'a> {
+            fn hash<H: Hasher>(&self, state: &mut H) {
+                self.expose().hash(state)
+            }
+        }
+
+        impl<'a> fmt::Debug for $name<'a> {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "{}({})", stringify!($name), REDACTED)
+            }
+        }
+
+        impl<'a> fmt::Display for $name<'a> {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str(REDACTED)
+            }
+        }
+
+        impl<'a> From<&'a str> for $name<'a> {
+            fn from(x: &'a str) -> $name<'a> {
+                $name(Cow::Borrowed(x))
+            }
+        }
+
+        impl<'a> From<&'a String> for $name<'a> {
+            fn from(x: &'a String) -> $name<'a> {
+                $name(Cow::Borrowed(x))
+            }
+        }
+
+        impl<'a> From<String> for $name<'a> {
+            fn from(x: String) -> $name<'a> {
+                $name(Cow::Owned(x))
+            }
+        }
+
+        impl<'a> From<Cow<'a, str>> for $name<'a> {
+            fn from(x: Cow<'a, str>) -> $name<'a> {
+                $name(x)
+            }
+        }
+
+        impl<'a, 'b> From<&'b $name<'a>> for $name<'b> {
+            fn from(x: &'b $name<'a>) -> $name<'b> {
+                $name(Cow::Borrowed(x.expose()))
+            }
+        }
+    }
+}
+
+secret_string! {
+    /// `oauth_consumer_secret`, which is the shared secret of the client credentials.
+    ///
+    /// # Example
+    /// ```
+    /// # use oauthcli::ConsumerSecret;
+    /// let secret = ConsumerSecret::new(String::from("kd94hf93k423kf44"));
+    /// assert_eq!(format!("{:?}", secret), "ConsumerSecret([REDACTED])");
+    /// assert_eq!(secret.expose(), "kd94hf93k423kf44");
+    /// ```
+    ConsumerSecret
+}
+
+secret_string! {
+    /// `oauth_token_secret` of the temporary credentials or the token credentials.
+    TokenSecret
+}
+
+/// DER-encoded private key such as the key of RSA-SHA1.
+pub struct PrivateKey(Vec<u8>);
+
+impl PrivateKey {
+    pub fn new(der: Vec<u8>) -> PrivateKey {
+        PrivateKey(der)
+    }
+
+    /// Returns the DER-encoded key.
+    pub fn expose(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl Drop for PrivateKey {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl Clone for PrivateKey {
+    fn clone(&self) -> PrivateKey {
+        PrivateKey(self.0.clone())
+    }
+}
+
+impl fmt::Debug for PrivateKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "PrivateKey({})", REDACTED)
+    }
+}
+
+impl fmt::Display for PrivateKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+impl From<Vec<u8>> for PrivateKey {
+    fn from(x: Vec<u8>) -> PrivateKey {
+        PrivateKey(x)
+    }
+}
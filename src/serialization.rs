@@ -0,0 +1,112 @@
+//! Implementations of `Serialize` and `Deserialize` which need validation.
+
+use alloc::fmt;
+use alloc::string::String;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde::de::{self, Visitor};
+use super::{ConsumerSecret, SignatureMethod, TokenSecret};
+#[cfg(feature = "std")] use super::{OAuthAuthorizationHeader, SignedRequest};
+
+/// Serialized as its name such as `"HMAC-SHA1"`.
+impl Serialize for SignatureMethod {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.to_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for SignatureMethod {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<SignatureMethod, D::Error> {
+        struct SignatureMethodVisitor;
+
+        impl<'de> Visitor<'de> for SignatureMethodVisitor {
+            type Value = SignatureMethod;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a name of oauth_signature_method")
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<SignatureMethod, E> {
+                v.parse().map_err(|_| E::unknown_variant(v, &["HMAC-SHA1", "PLAINTEXT"]))
+            }
+        }
+
+        deserializer.deserialize_str(SignatureMethodVisitor)
+    }
+}
+
+macro_rules! serde_secret {
+    ($name:ident) => {
+        /// Serialized as the plain secret.
+        impl<'a> Serialize for $name<'a> {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_str(self.expose())
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $name<'static> {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<$name<'static>, D::Error> {
+                String::deserialize(deserializer).map($name::from)
+            }
+        }
+    }
+}
+
+serde_secret!(ConsumerSecret);
+serde_secret!(TokenSecret);
+
+/// Deserializes `auth-param`s with `parse`.
+#[cfg(feature = "std")]
+fn deserialize_auth_params<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+    where D: Deserializer<'de>, T: ::std::str::FromStr, T::Err: fmt::Display
+{
+    use std::marker::PhantomData;
+
+    struct AuthParamsVisitor<T>(PhantomData<T>);
+
+    impl<'de, T> Visitor<'de> for AuthParamsVisitor<T>
+        where T: ::std::str::FromStr, T::Err: fmt::Display
+    {
+        type Value = T;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("auth-params of OAuth")
+        }
+
+        fn visit_str<E: de::Error>(self, v: &str) -> Result<T, E> {
+            v.parse().map_err(E::custom)
+        }
+    }
+
+    deserializer.deserialize_str(AuthParamsVisitor(PhantomData))
+}
+
+/// Serialized as `auth_param()`.
+#[cfg(feature = "std")]
+impl Serialize for OAuthAuthorizationHeader {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.auth_param())
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'de> Deserialize<'de> for OAuthAuthorizationHeader {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<OAuthAuthorizationHeader, D::Error> {
+        deserialize_auth_params(deserializer)
+    }
+}
+
+/// Serialized as `to_header().auth_param()`.
+#[cfg(feature = "std")]
+impl Serialize for SignedRequest {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.to_header().auth_param())
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'de> Deserialize<'de> for SignedRequest {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<SignedRequest, D::Error> {
+        let header: OAuthAuthorizationHeader = try!(deserialize_auth_params(deserializer));
+        SignedRequest::from_header(&header).map_err(de::Error::custom)
+    }
+}
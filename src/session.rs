@@ -0,0 +1,95 @@
+//! [OAuth Session 1.0](http://oauth.googlecode.com/svn/spec/ext/session/1.0/drafts/1/spec.html)
+
+use std::borrow::Cow;
+use url::Url;
+use super::{ConsumerSecret, OAuthAuthorizationHeaderBuilder, OAuthTokenResponse, SignatureMethod, TokenSecret};
+
+/// Access token which can be refreshed with `oauth_session_handle`.
+///
+/// # Example
+/// ```
+/// # extern crate oauthcli;
+/// # extern crate url;
+/// # use oauthcli::*;
+/// # fn main() {
+/// let res = OAuthTokenResponse::from_body(
+///     "oauth_token=token&oauth_token_secret=secret&oauth_session_handle=handle&oauth_expires_in=3600"
+/// ).unwrap();
+/// let session = OAuthSession::from_token_response(&res, 1500000000).unwrap();
+///
+/// if session.needs_refresh(1500003500, 300) {
+///     let url = url::Url::parse("https://api.example.com/oauth/get_token").unwrap();
+///     let header = session.refresh_request(&url, "consumer", "secret", SignatureMethod::HmacSha1).finish();
+/// }
+/// # }
+/// ```
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct OAuthSession {
+    /// `oauth_token`
+    pub token: String,
+    /// `oauth_token_secret`
+    pub token_secret: TokenSecret<'static>,
+    /// `oauth_session_handle`
+    pub session_handle: String,
+    /// The time when the access token expires, in seconds since the Unix epoch.
+    pub expires_at: Option<u64>,
+    /// The time when the session handle expires, in seconds since the Unix epoch.
+    pub authorization_expires_at: Option<u64>
+}
+
+impl OAuthSession {
+    /// Creates `OAuthSession` from the response received at `now`.
+    /// Returns `None` if the response does not contain `oauth_session_handle`.
+    pub fn from_token_response(res: &OAuthTokenResponse, now: u64) -> Option<OAuthSession> {
+        res.session_handle.as_ref().map(|x| OAuthSession {
+            token: res.token.clone(),
+            token_secret: res.token_secret.clone(),
+            session_handle: x.clone(),
+            expires_at: res.expires_in.map(|x| now.saturating_add(x)),
+            authorization_expires_at: res.authorization_expires_in.map(|x| now.saturating_add(x))
+        })
+    }
+
+    /// Replaces the token with the refreshed one received at `now`.
+    /// The session handle is kept if the response does not contain a new one.
+    pub fn update(&mut self, res: &OAuthTokenResponse, now: u64) {
+        self.token = res.token.clone();
+        self.token_secret = res.token_secret.clone();
+        if let Some(ref x) = res.session_handle { self.session_handle = x.clone(); }
+        self.expires_at = res.expires_in.map(|x| now.saturating_add(x));
+        if let Some(x) = res.authorization_expires_in {
+            self.authorization_expires_at = Some(now.saturating_add(x));
+        }
+    }
+
+    /// Returns `true` if the access token expires within `margin` seconds from `now`
+    /// and the session handle is still valid.
+    pub fn needs_refresh(&self, now: u64, margin: u64) -> bool {
+        let expiring = match self.expires_at {
+            Some(x) => now.saturating_add(margin) >= x,
+            None => false
+        };
+        expiring && self.can_refresh(now)
+    }
+
+    /// Returns `true` if the session handle has not expired at `now`.
+    pub fn can_refresh(&self, now: u64) -> bool {
+        match self.authorization_expires_at {
+            Some(x) => now < x,
+            None => true
+        }
+    }
+
+    /// Creates a builder for the request to refresh the access token.
+    pub fn refresh_request<'a, C, S>(&'a self, url: &'a Url, consumer_key: C, consumer_secret: S, signature_method: SignatureMethod)
+        -> OAuthAuthorizationHeaderBuilder<'a>
+        where C: Into<Cow<'a, str>>, S: Into<ConsumerSecret<'a>>
+    {
+        let mut builder = OAuthAuthorizationHeaderBuilder::new("POST", url, consumer_key, consumer_secret, signature_method);
+        builder
+            .token(&self.token[..], &self.token_secret)
+            .session_handle(&self.session_handle[..]);
+        builder
+    }
+}
@@ -0,0 +1,260 @@
+//! Signature methods as `Signer`s, and `SignerRegistry` which maps their names to them.
+//!
+//! # Example
+//! ```
+//! # extern crate oauthcli;
+//! # extern crate url;
+//! # use oauthcli::*;
+//! # use oauthcli::signer::*;
+//! # fn main() {
+//! let mut registry = SignerRegistry::new();
+//! registry.unregister("PLAINTEXT");
+//! assert!(registry.get("HMAC-SHA256").is_some());
+//! assert!(registry.get("PLAINTEXT").is_none());
+//!
+//! let url = url::Url::parse("http://example.com/").unwrap();
+//! let header =
+//!     OAuthAuthorizationHeaderBuilder::new(
+//!         "GET", &url, "consumer", "secret", SignatureMethod::HmacSha1)
+//!     .registered_signature_method(&registry, "HMAC-SHA256").unwrap()
+//!     .finish();
+//! assert!(header.auth_param().contains("oauth_signature_method=\"HMAC-SHA256\""));
+//! # }
+//! ```
+
+use alloc::boxed::Box;
+use alloc::fmt;
+use alloc::string::String;
+use alloc::vec::Vec;
+#[cfg(feature = "std")] use std::error::Error;
+use zeroize::Zeroize;
+use super::{PrivateKey, SignatureMethod};
+use super::crypto::{self, CryptoError};
+use super::signing::percent_encode;
+
+/// Calculates and verifies `oauth_signature` of a signature method.
+pub trait Signer: Send + Sync {
+    /// Calculates `oauth_signature` from the Signature Base String.
+    ///
+    /// Errors should depend only on the key and the backend, not on the inputs,
+    /// because `OAuthAuthorizationHeaderBuilder::registered_signature_method` tries the signer once.
+    fn sign(&self, base_string: &str, consumer_secret: &str, token_secret: Option<&str>) -> Result<String, CryptoError>;
+
+    /// Verifies `signature`.
+    /// The default implementation compares it with the result of `sign` in constant time.
+    fn verify(&self, base_string: &str, consumer_secret: &str, token_secret: Option<&str>, signature: &str) -> bool {
+        match self.sign(base_string, consumer_secret, token_secret) {
+            Ok(x) => crypto::constant_time_eq(x.as_bytes(), signature.as_bytes()),
+            Err(_) => false
+        }
+    }
+
+    /// Returns `false` if the signature does not depend on the Signature Base String,
+    /// so that the base string is not calculated.
+    fn uses_base_string(&self) -> bool {
+        true
+    }
+}
+
+/// The key of HMAC and PLAINTEXT.
+fn key(consumer_secret: &str, token_secret: Option<&str>) -> String {
+    let mut key: String = percent_encode(consumer_secret.as_bytes()).collect();
+    key.push('&');
+
+    if let Some(x) = token_secret {
+        key.extend(percent_encode(x.as_bytes()));
+    }
+
+    key
+}
+
+/// HMAC-SHA1 of [RFC 5849 section 3.4.2](https://tools.ietf.org/html/rfc5849#section-3.4.2).
+#[derive(Copy, Debug, PartialEq, Eq, Clone, Hash)]
+pub struct HmacSha1Signer;
+
+impl Signer for HmacSha1Signer {
+    fn sign(&self, base_string: &str, consumer_secret: &str, token_secret: Option<&str>) -> Result<String, CryptoError> {
+        let mut key = key(consumer_secret, token_secret);
+        let signature = crypto::hmac_sha1(key.as_bytes(), base_string.as_bytes());
+        key.zeroize();
+        Ok(base64::encode(&signature))
+    }
+}
+
+/// HMAC-SHA256, which is HMAC-SHA1 with SHA-256 instead of SHA-1.
+#[derive(Copy, Debug, PartialEq, Eq, Clone, Hash)]
+pub struct HmacSha256Signer;
+
+impl Signer for HmacSha256Signer {
+    fn sign(&self, base_string: &str, consumer_secret: &str, token_secret: Option<&str>) -> Result<String, CryptoError> {
+        let mut key = key(consumer_secret, token_secret);
+        let signature = crypto::hmac_sha256(key.as_bytes(), base_string.as_bytes());
+        key.zeroize();
+        Ok(base64::encode(&signature))
+    }
+}
+
+/// PLAINTEXT of [RFC 5849 section 3.4.4](https://tools.ietf.org/html/rfc5849#section-3.4.4).
+#[derive(Copy, Debug, PartialEq, Eq, Clone, Hash)]
+pub struct PlaintextSigner;
+
+impl Signer for PlaintextSigner {
+    fn sign(&self, _: &str, consumer_secret: &str, token_secret: Option<&str>) -> Result<String, CryptoError> {
+        Ok(key(consumer_secret, token_secret))
+    }
+
+    fn uses_base_string(&self) -> bool {
+        false
+    }
+}
+
+/// RSA-SHA1 of [RFC 5849 section 3.4.3](https://tools.ietf.org/html/rfc5849#section-3.4.3),
+/// which ignores the secrets.
+///
+/// The keys are DER-encoded PKCS #1. The `ring` crypto backend supports only `verifier`.
+#[derive(Debug, Clone)]
+pub struct RsaSha1Signer {
+    private_key: Option<PrivateKey>,
+    public_key: Option<Vec<u8>>
+}
+
+impl RsaSha1Signer {
+    /// Creates a signer for Consumers with the private key.
+    pub fn new<K: Into<PrivateKey>>(private_key: K) -> RsaSha1Signer {
+        RsaSha1Signer { private_key: Some(private_key.into()), public_key: None }
+    }
+
+    /// Creates a signer for Service Providers, which only verifies signatures with the public key.
+    pub fn verifier(public_key: Vec<u8>) -> RsaSha1Signer {
+        RsaSha1Signer { private_key: None, public_key: Some(public_key) }
+    }
+}
+
+impl Signer for RsaSha1Signer {
+    fn sign(&self, base_string: &str, _: &str, _: Option<&str>) -> Result<String, CryptoError> {
+        let private_key = try!(self.private_key.as_ref().ok_or(CryptoError::InvalidKey));
+        crypto::rsa_sha1_sign(private_key.expose(), base_string.as_bytes())
+            .map(|x| base64::encode(&x))
+    }
+
+    fn verify(&self, base_string: &str, _: &str, _: Option<&str>, signature: &str) -> bool {
+        let public_key = match self.public_key {
+            Some(ref x) => x,
+            None => return false
+        };
+
+        match base64::decode(signature) {
+            Ok(x) => crypto::rsa_sha1_verify(public_key, base_string.as_bytes(), &x).unwrap_or(false),
+            Err(_) => false
+        }
+    }
+}
+
+static HMAC_SHA1: HmacSha1Signer = HmacSha1Signer;
+static PLAINTEXT: PlaintextSigner = PlaintextSigner;
+
+impl SignatureMethod {
+    /// The built-in `Signer` of the signature method.
+    pub fn signer(&self) -> &'static Signer {
+        match *self {
+            SignatureMethod::HmacSha1 => &HMAC_SHA1,
+            SignatureMethod::Plaintext => &PLAINTEXT
+        }
+    }
+}
+
+/// Map from names of `oauth_signature_method` to `Signer`s.
+/// The names are case-sensitive.
+pub struct SignerRegistry {
+    signers: Vec<(String, Box<Signer>)>
+}
+
+impl SignerRegistry {
+    /// Creates a registry with the built-in signature methods:
+    /// HMAC-SHA1, HMAC-SHA256 and PLAINTEXT.
+    pub fn new() -> SignerRegistry {
+        let mut registry = SignerRegistry::empty();
+        registry
+            .register("HMAC-SHA1", HmacSha1Signer)
+            .register("HMAC-SHA256", HmacSha256Signer)
+            .register("PLAINTEXT", PlaintextSigner);
+        registry
+    }
+
+    /// Creates a registry without any signature method.
+    pub fn empty() -> SignerRegistry {
+        SignerRegistry { signers: Vec::new() }
+    }
+
+    /// Registers `signer` as `name`, replacing the existing one.
+    pub fn register<N, S>(&mut self, name: N, signer: S) -> &mut Self
+        where N: Into<String>, S: Signer + 'static
+    {
+        let name = name.into();
+        self.unregister(&name);
+        self.signers.push((name, Box::new(signer)));
+        self
+    }
+
+    /// Removes the signer registered as `name`.
+    pub fn unregister(&mut self, name: &str) -> &mut Self {
+        self.signers.retain(|&(ref k, _)| k != name);
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Signer> {
+        self.signers.iter()
+            .find(|&&(ref k, _)| k == name)
+            .map(|&(_, ref v)| &**v)
+    }
+}
+
+impl fmt::Debug for SignerRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_set().entries(self.signers.iter().map(|&(ref k, _)| k)).finish()
+    }
+}
+
+impl Default for SignerRegistry {
+    fn default() -> SignerRegistry {
+        SignerRegistry::new()
+    }
+}
+
+/// The error of `OAuthAuthorizationHeaderBuilder::registered_signature_method`.
+#[derive(Copy, Debug, PartialEq, Eq, Clone, Hash)]
+pub enum SignerError {
+    /// The signature method is not registered.
+    NotRegistered,
+    /// The signer failed to sign.
+    Crypto(CryptoError)
+}
+
+impl SignerError {
+    fn message(&self) -> &'static str {
+        match *self {
+            SignerError::NotRegistered => "The signature method is not registered",
+            SignerError::Crypto(_) => "The signer failed to sign"
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Error for SignerError {
+    fn description(&self) -> &str {
+        self.message()
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            SignerError::Crypto(ref x) => Some(x),
+            _ => None
+        }
+    }
+}
+
+impl fmt::Display for SignerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.message())
+    }
+}
@@ -0,0 +1,262 @@
+//! The core of the signing process, which depends only on `core` and `alloc`.
+//!
+//! Without `std` feature, `OAuthAuthorizationHeaderBuilder` is not available,
+//! so the caller supplies `oauth_timestamp` and `oauth_nonce`, and builds the request
+//! with the functions in this module.
+//!
+//! # Example
+//! ```
+//! use oauthcli::SignatureMethod;
+//! use oauthcli::signing;
+//!
+//! let oauth_params = [
+//!     ("oauth_consumer_key", "dpf43f3p2l4k3l03"),
+//!     ("oauth_token", "nnch734d00sl2jdk"),
+//!     ("oauth_signature_method", "HMAC-SHA1"),
+//!     ("oauth_timestamp", "137131202"),
+//!     ("oauth_nonce", "chapoH")
+//! ];
+//! let request_params = [("file", "vacation.jpg"), ("size", "original")];
+//!
+//! let base_string = signing::signature_base_string(
+//!     "GET",
+//!     &signing::base_string_uri("http", "photos.example.net", None, "/photos"),
+//!     oauth_params.iter().chain(request_params.iter()).map(|&(k, v)| (k.as_bytes(), v.as_bytes())));
+//! let signature = signing::sign(SignatureMethod::HmacSha1, &base_string, "kd94hf93k423kf44", Some("pfkkdhi9sl3r4s00"));
+//! assert_eq!(signature, "MdpQcU8iPSUjWoN/UDMsK2sui9I=");
+//!
+//! let auth_param = signing::auth_param(Some("Photos"), oauth_params.iter().cloned(), &signature);
+//! assert!(auth_param.ends_with("oauth_signature=\"MdpQcU8iPSUjWoN%2FUDMsK2sui9I%3D\""));
+//! ```
+
+use alloc::borrow::Cow;
+use alloc::fmt::{self, Write};
+use alloc::str;
+use alloc::string::String;
+use alloc::vec::Vec;
+use super::SignatureMethod;
+use super::crypto;
+
+static PERCENT_ENCODED: [&'static str; 16] = [
+    "%00%01%02%03%04%05%06%07%08%09%0A%0B%0C%0D%0E%0F",
+    "%10%11%12%13%14%15%16%17%18%19%1A%1B%1C%1D%1E%1F",
+    "%20%21%22%23%24%25%26%27%28%29%2A%2B%2C%2D%2E%2F",
+    "%30%31%32%33%34%35%36%37%38%39%3A%3B%3C%3D%3E%3F",
+    "%40%41%42%43%44%45%46%47%48%49%4A%4B%4C%4D%4E%4F",
+    "%50%51%52%53%54%55%56%57%58%59%5A%5B%5C%5D%5E%5F",
+    "%60%61%62%63%64%65%66%67%68%69%6A%6B%6C%6D%6E%6F",
+    "%70%71%72%73%74%75%76%77%78%79%7A%7B%7C%7D%7E%7F",
+    "%80%81%82%83%84%85%86%87%88%89%8A%8B%8C%8D%8E%8F",
+    "%90%91%92%93%94%95%96%97%98%99%9A%9B%9C%9D%9E%9F",
+    "%A0%A1%A2%A3%A4%A5%A6%A7%A8%A9%AA%AB%AC%AD%AE%AF",
+    "%B0%B1%B2%B3%B4%B5%B6%B7%B8%B9%BA%BB%BC%BD%BE%BF",
+    "%C0%C1%C2%C3%C4%C5%C6%C7%C8%C9%CA%CB%CC%CD%CE%CF",
+    "%D0%D1%D2%D3%D4%D5%D6%D7%D8%D9%DA%DB%DC%DD%DE%DF",
+    "%E0%E1%E2%E3%E4%E5%E6%E7%E8%E9%EA%EB%EC%ED%EE%EF",
+    "%F0%F1%F2%F3%F4%F5%F6%F7%F8%F9%FA%FB%FC%FD%FE%FF",
+];
+
+/// Returns `true` if `byte` is an unreserved character of
+/// [RFC 5849 section 3.6](http://tools.ietf.org/html/rfc5849#section-3.6).
+pub(crate) fn is_unreserved(byte: u8) -> bool {
+    (byte >= 0x30 && byte <= 0x39)
+    || (byte >= 0x41 && byte <= 0x5A)
+    || (byte >= 0x61 && byte <= 0x7A)
+    || byte == 0x2D || byte == 0x2E
+    || byte == 0x5F || byte == 0x7E
+}
+
+/// The result of `percent_encode`, which yields string slices and implements `Display`.
+#[derive(Debug, Clone)]
+pub struct PercentEncode<'a> {
+    bytes: &'a [u8]
+}
+
+impl<'a> Iterator for PercentEncode<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        let first = match self.bytes.first() {
+            Some(&x) => x,
+            None => return None
+        };
+
+        if is_unreserved(first) {
+            let len = self.bytes.iter().position(|&x| !is_unreserved(x)).unwrap_or(self.bytes.len());
+            let (unchanged, rest) = self.bytes.split_at(len);
+            self.bytes = rest;
+            // Unreserved characters are ASCII
+            Some(str::from_utf8(unchanged).unwrap())
+        } else {
+            self.bytes = &self.bytes[1..];
+            let row = PERCENT_ENCODED[(first >> 4) as usize];
+            let i = (first & 0x0F) as usize * 3;
+            Some(&row[i..i + 3])
+        }
+    }
+}
+
+impl<'a> fmt::Display for PercentEncode<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for x in self.clone() {
+            try!(f.write_str(x));
+        }
+        Ok(())
+    }
+}
+
+/// Encodes `input` with `OAUTH_ENCODE_SET`.
+pub fn percent_encode<'a>(input: &'a [u8]) -> PercentEncode<'a> {
+    PercentEncode { bytes: input }
+}
+
+/// Base String URI of [RFC 5849 section 3.4.1.2](https://tools.ietf.org/html/rfc5849#section-3.4.1.2).
+///
+/// `path` must not contain the query.
+///
+/// # Panics
+/// This function will panic if `scheme` is not `"http"` or `"https"`.
+pub fn base_string_uri(scheme: &str, host: &str, port: Option<u16>, path: &str) -> String {
+    let scheme = scheme.to_ascii_lowercase();
+
+    let mut result = String::with_capacity(scheme.len() + host.len() + path.len() + 9);
+    result.push_str(&scheme);
+    result.push_str("://");
+    result.push_str(&host.to_ascii_lowercase());
+
+    if let Some(p) = port {
+        match (&scheme[..], p) {
+            ("http", 80) | ("https", 443) => (),
+            ("http", p) | ("https", p) => write!(&mut result, ":{}", p).unwrap(),
+            _ => panic!("The scheme is not \"http\" or \"https\"")
+        }
+    } else if scheme != "http" && scheme != "https" {
+        panic!("The scheme is not \"http\" or \"https\"");
+    }
+
+    result.push_str(if path.len() > 0 { path } else { "/" });
+    result
+}
+
+pub(crate) struct PercentEncodedParameters<'a>(pub Vec<(Cow<'a, str>, Cow<'a, str>)>);
+
+#[cfg(all(test, feature = "std"))]
+pub(crate) fn percent_encode_parameters<'a, P>(params: P) -> PercentEncodedParameters<'a>
+    where P: Iterator<Item = (Cow<'a, str>, Cow<'a, str>)>
+{
+    percent_encode_byte_parameters(params.map(|(k, v)| (str_to_bytes(k), str_to_bytes(v))))
+}
+
+pub(crate) fn percent_encode_byte_parameters<'a, P>(params: P) -> PercentEncodedParameters<'a>
+    where P: Iterator<Item = (Cow<'a, [u8]>, Cow<'a, [u8]>)>
+{
+    PercentEncodedParameters(
+        params
+            .map(|(k, v)| (percent_encode(&k).collect::<String>().into(), percent_encode(&v).collect::<String>().into()))
+            .collect()
+    )
+}
+
+#[cfg(feature = "std")]
+pub(crate) fn str_to_bytes(s: Cow<str>) -> Cow<[u8]> {
+    match s {
+        Cow::Borrowed(x) => Cow::Borrowed(x.as_bytes()),
+        Cow::Owned(x) => Cow::Owned(x.into_bytes())
+    }
+}
+
+pub(crate) fn normalize_parameters<'a>(params: PercentEncodedParameters<'a>) -> String {
+    let mut mutparams = params.0;
+    let mut result = String::new();
+
+    if mutparams.len() > 0 {
+        mutparams.sort();
+
+        let mut first = true;
+        for (key, val) in mutparams.into_iter() {
+            if first { first = false; }
+            else { result.push('&'); }
+
+            result.push_str(&key);
+            result.push('=');
+            result.push_str(&val);
+        }
+    }
+
+    result
+}
+
+pub(crate) fn encoded_signature_base_string(method: &str, base_string_uri: &str, params: PercentEncodedParameters) -> String {
+    let mut base_string = method.to_ascii_uppercase();
+    base_string.push('&');
+    base_string.extend(percent_encode(base_string_uri.as_bytes()));
+    base_string.push('&');
+    base_string.extend(percent_encode(normalize_parameters(params).as_bytes()));
+    base_string
+}
+
+/// Signature Base String of [RFC 5849 section 3.4.1](https://tools.ietf.org/html/rfc5849#section-3.4.1).
+///
+/// `params` are the decoded protocol parameters except `realm` and `oauth_signature`,
+/// the query parameters and the form-encoded body parameters.
+pub fn signature_base_string<'a, P>(method: &str, base_string_uri: &str, params: P) -> String
+    where P: IntoIterator<Item = (&'a [u8], &'a [u8])>
+{
+    let params = percent_encode_byte_parameters(
+        params.into_iter().map(|(k, v)| (Cow::Borrowed(k), Cow::Borrowed(v))));
+    encoded_signature_base_string(method, base_string_uri, params)
+}
+
+/// Calculates `oauth_signature` from the result of `signature_base_string`.
+/// `base_string` is ignored if `signature_method` is PLAINTEXT.
+pub fn sign(signature_method: SignatureMethod, base_string: &str, consumer_secret: &str, token_secret: Option<&str>) -> String {
+    signature_method.signer().sign(base_string, consumer_secret, token_secret)
+        .expect("The built-in signers do not fail")
+}
+
+/// `oauth_body_hash` of [OAuth Request Body Hash](https://tools.ietf.org/id/draft-eaton-oauth-bodyhash-00.html).
+pub fn body_hash(body: &[u8]) -> String {
+    base64::encode(&crypto::sha1(body))
+}
+
+/// Joins key-value pairs as `auth-param`s, quoting the values encoded with `OAUTH_ENCODE_SET`.
+///
+/// `realm` is not encoded but escaped as RFC 2617 quoted-string
+/// ([RFC 5849 section 3.5.1](https://tools.ietf.org/html/rfc5849#section-3.5.1)).
+pub(crate) fn format_auth_params<'a, P>(params: P) -> String
+    where P: Iterator<Item = (&'a str, &'a str)>
+{
+    let mut result = String::new();
+    let mut first = true;
+
+    for (k, v) in params {
+        if first { first = false; }
+        else { result.push(','); }
+
+        if k == "realm" {
+            result.push_str("realm=\"");
+            for c in v.chars() {
+                if c == '"' || c == '\\' { result.push('\\'); }
+                result.push(c);
+            }
+            result.push('"');
+        } else {
+            write!(&mut result, "{}=\"{}\"",
+                percent_encode(k.as_bytes()), percent_encode(v.as_bytes())).unwrap();
+        }
+    }
+
+    result
+}
+
+/// Formats `auth-param` of `Authorization` header, which follows `"OAuth "`.
+///
+/// `oauth_params` are the protocol parameters except `realm` and `oauth_signature`.
+pub fn auth_param<'a, P>(realm: Option<&'a str>, oauth_params: P, signature: &'a str) -> String
+    where P: IntoIterator<Item = (&'a str, &'a str)>
+{
+    format_auth_params(
+        realm.map(|x| ("realm", x)).into_iter()
+            .chain(oauth_params)
+            .chain(Some(("oauth_signature", signature))))
+}
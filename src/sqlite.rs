@@ -0,0 +1,288 @@
+//! Stores in a SQLite database, which survive restarts of the process.
+//!
+//! `SqliteStore` implements `PendingTokenStore` for consumers, and the stores of `provider::Provider`.
+//! The tables are prefixed with `oauthcli_`, and are created or migrated when the database is opened.
+//!
+//! # Example
+//! ```
+//! # extern crate oauthcli;
+//! # extern crate url;
+//! # use oauthcli::*;
+//! # use oauthcli::provider::*;
+//! # use oauthcli::sqlite::SqliteStore;
+//! # fn main() {
+//! let store = SqliteStore::open_in_memory().unwrap();
+//!
+//! // Save the token credentials of a user after the authorization
+//! store.insert_access_token(&AccessTokenRecord {
+//!     consumer_key: "dpf43f3p2l4k3l03".to_owned(),
+//!     credentials: TokenCredentials::new("nnch734d00sl2jdk", "pfkkdhi9sl3r4s00"),
+//!     user: "jane".to_owned()
+//! }).unwrap();
+//!
+//! let record = store.access_token("nnch734d00sl2jdk").unwrap().unwrap();
+//! let url = url::Url::parse("http://photos.example.net/photos?file=vacation.jpg&size=original").unwrap();
+//! let header =
+//!     OAuthAuthorizationHeaderBuilder::new(
+//!         "GET", &url, &record.consumer_key[..], "kd94hf93k423kf44", SignatureMethod::HmacSha1)
+//!     .token(&record.credentials.token[..], &record.credentials.secret)
+//!     .finish();
+//! # }
+//! ```
+
+use std::convert::TryFrom;
+use std::path::Path;
+use std::sync::Mutex;
+use rusqlite::{Connection, OptionalExtension, Row, TransactionBehavior};
+use super::{ClientCredentials, TokenCredentials, TokenSecret};
+use super::provider::{AccessTokenRecord, AccessTokenStore, ConsumerRecord, ConsumerStore};
+use super::provider::{NonceStore, RequestTokenRecord, RequestTokenStore};
+use super::store::{PendingTokenStore, lock};
+
+/// The schema of each version. Append a new version instead of editing the existing ones.
+const MIGRATIONS: &'static [&'static str] = &[
+    "CREATE TABLE oauthcli_consumers (
+        key TEXT PRIMARY KEY NOT NULL,
+        secret TEXT NOT NULL,
+        callback_prefix TEXT
+    );
+    CREATE TABLE oauthcli_request_tokens (
+        token TEXT PRIMARY KEY NOT NULL,
+        secret TEXT NOT NULL,
+        consumer_key TEXT NOT NULL,
+        callback TEXT NOT NULL,
+        user TEXT,
+        verifier TEXT,
+        expires_at INTEGER NOT NULL
+    );
+    CREATE INDEX oauthcli_request_tokens_expires_at ON oauthcli_request_tokens (expires_at);
+    CREATE TABLE oauthcli_access_tokens (
+        token TEXT PRIMARY KEY NOT NULL,
+        secret TEXT NOT NULL,
+        consumer_key TEXT NOT NULL,
+        user TEXT NOT NULL
+    );
+    CREATE TABLE oauthcli_pending_tokens (
+        token TEXT PRIMARY KEY NOT NULL,
+        secret TEXT NOT NULL,
+        expires_at INTEGER NOT NULL
+    );
+    CREATE INDEX oauthcli_pending_tokens_expires_at ON oauthcli_pending_tokens (expires_at);
+    CREATE TABLE oauthcli_nonces (
+        consumer_key TEXT NOT NULL,
+        token TEXT NOT NULL,
+        timestamp INTEGER NOT NULL,
+        nonce TEXT NOT NULL,
+        PRIMARY KEY (consumer_key, token, timestamp, nonce)
+    );
+    CREATE INDEX oauthcli_nonces_timestamp ON oauthcli_nonces (timestamp);"
+];
+
+fn migrate(conn: &mut Connection) -> rusqlite::Result<()> {
+    // IMMEDIATE blocks the other processes opening the same database
+    let tx = try!(conn.transaction_with_behavior(TransactionBehavior::Immediate));
+    try!(tx.execute_batch("CREATE TABLE IF NOT EXISTS oauthcli_migrations (version INTEGER PRIMARY KEY NOT NULL)"));
+    let current: i64 = try!(tx.query_row("SELECT COALESCE(MAX(version), 0) FROM oauthcli_migrations", [], |row| row.get(0)));
+
+    // Do not touch the schema which this version does not know
+    if current > MIGRATIONS.len() as i64 {
+        return Err(rusqlite::Error::SqliteFailure(
+            rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_ERROR),
+            Some(format!("The database is migrated to version {} by a newer oauthcli", current))
+        ));
+    }
+
+    for (i, sql) in MIGRATIONS.iter().enumerate().skip(current as usize) {
+        try!(tx.execute_batch(sql));
+        try!(tx.execute("INSERT INTO oauthcli_migrations (version) VALUES (?1)", [i as i64 + 1]));
+    }
+
+    tx.commit()
+}
+
+fn request_token_record(row: &Row) -> rusqlite::Result<RequestTokenRecord> {
+    Ok(RequestTokenRecord {
+        consumer_key: try!(row.get(2)),
+        credentials: TokenCredentials { token: try!(row.get(0)), secret: TokenSecret::new(try!(row.get::<_, String>(1))) },
+        callback: try!(row.get(3)),
+        user: try!(row.get(4)),
+        verifier: try!(row.get(5)),
+        expires_at: try!(row.get::<_, i64>(6)) as u64
+    })
+}
+
+/// `PendingTokenStore`, `ConsumerStore`, `RequestTokenStore`, `AccessTokenStore` and `NonceStore`
+/// in a SQLite database.
+///
+/// The secrets are saved as plain text, so restrict the access to the database file.
+#[derive(Debug)]
+pub struct SqliteStore {
+    conn: Mutex<Connection>,
+    pending_token_ttl: u64
+}
+
+impl SqliteStore {
+    /// Opens or creates the database in `path`.
+    /// Returns `Err` if the database has been migrated by a newer version of this crate.
+    pub fn open<P: AsRef<Path>>(path: P) -> rusqlite::Result<SqliteStore> {
+        SqliteStore::from_connection(try!(Connection::open(path)))
+    }
+
+    /// Creates a database in memory, which is lost when the store is dropped.
+    pub fn open_in_memory() -> rusqlite::Result<SqliteStore> {
+        SqliteStore::from_connection(try!(Connection::open_in_memory()))
+    }
+
+    /// Uses the opened database, which may also have the tables of the application.
+    pub fn from_connection(mut conn: Connection) -> rusqlite::Result<SqliteStore> {
+        try!(migrate(&mut conn));
+        Ok(SqliteStore { conn: Mutex::new(conn), pending_token_ttl: 600 })
+    }
+
+    /// Sets the seconds until the secrets inserted by `PendingTokenStore::insert` expire.
+    /// The default is 10 minutes.
+    pub fn pending_token_ttl(&mut self, seconds: u64) -> &mut Self {
+        self.pending_token_ttl = seconds;
+        self
+    }
+
+    /// Registers the consumer, or replaces the consumer with the same key.
+    pub fn insert_consumer(&self, record: &ConsumerRecord) -> rusqlite::Result<()> {
+        try!(lock(&self.conn).execute(
+            "INSERT OR REPLACE INTO oauthcli_consumers (key, secret, callback_prefix) VALUES (?1, ?2, ?3)",
+            params![record.credentials.key, record.credentials.secret.expose(), record.callback_prefix]));
+        Ok(())
+    }
+
+    /// Unregisters the consumer. Returns `false` if the consumer is not found.
+    pub fn remove_consumer(&self, key: &str) -> rusqlite::Result<bool> {
+        lock(&self.conn).execute("DELETE FROM oauthcli_consumers WHERE key = ?1", [key]).map(|x| x > 0)
+    }
+}
+
+/// SQLite has only signed integers, so the times after `i64::MAX` are saturated.
+fn to_sql_time(x: u64) -> i64 {
+    i64::try_from(x).unwrap_or(i64::MAX)
+}
+
+impl PendingTokenStore for SqliteStore {
+    type Error = rusqlite::Error;
+
+    fn insert(&self, token: &str, secret: &TokenSecret, now: u64) -> rusqlite::Result<()> {
+        let expires_at = to_sql_time(now.saturating_add(self.pending_token_ttl));
+        try!(lock(&self.conn).execute(
+            "INSERT OR REPLACE INTO oauthcli_pending_tokens (token, secret, expires_at) VALUES (?1, ?2, ?3)",
+            params![token, secret.expose(), expires_at]));
+        Ok(())
+    }
+
+    fn take(&self, token: &str, now: u64) -> rusqlite::Result<Option<TokenSecret<'static>>> {
+        let entry = try!(lock(&self.conn).query_row(
+            "DELETE FROM oauthcli_pending_tokens WHERE token = ?1 RETURNING secret, expires_at",
+            [token], |row| Ok((try!(row.get::<_, String>(0)), try!(row.get::<_, i64>(1)) as u64))).optional());
+
+        Ok(match entry {
+            Some((secret, expires_at)) if now < expires_at => Some(TokenSecret::new(secret)),
+            _ => None
+        })
+    }
+
+    fn remove_expired(&self, now: u64) -> rusqlite::Result<()> {
+        try!(lock(&self.conn).execute("DELETE FROM oauthcli_pending_tokens WHERE expires_at <= ?1", [to_sql_time(now)]));
+        Ok(())
+    }
+}
+
+impl ConsumerStore for SqliteStore {
+    type Error = rusqlite::Error;
+
+    fn consumer(&self, key: &str) -> rusqlite::Result<Option<ConsumerRecord>> {
+        lock(&self.conn).query_row(
+            "SELECT key, secret, callback_prefix FROM oauthcli_consumers WHERE key = ?1", [key],
+            |row| Ok(ConsumerRecord {
+                credentials: ClientCredentials::new(try!(row.get::<_, String>(0)), try!(row.get::<_, String>(1))),
+                callback_prefix: try!(row.get(2))
+            })).optional()
+    }
+}
+
+impl RequestTokenStore for SqliteStore {
+    type Error = rusqlite::Error;
+
+    fn insert_request_token(&self, record: &RequestTokenRecord) -> rusqlite::Result<()> {
+        try!(lock(&self.conn).execute(
+            "INSERT INTO oauthcli_request_tokens (token, secret, consumer_key, callback, user, verifier, expires_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![record.credentials.token, record.credentials.secret.expose(), record.consumer_key,
+                record.callback, record.user, record.verifier, to_sql_time(record.expires_at)]));
+        Ok(())
+    }
+
+    fn request_token(&self, token: &str, now: u64) -> rusqlite::Result<Option<RequestTokenRecord>> {
+        lock(&self.conn).query_row(
+            "SELECT token, secret, consumer_key, callback, user, verifier, expires_at FROM oauthcli_request_tokens
+            WHERE token = ?1 AND expires_at > ?2",
+            params![token, to_sql_time(now)], request_token_record).optional()
+    }
+
+    fn authorize_request_token(&self, token: &str, user: &str, verifier: &str, now: u64) -> rusqlite::Result<bool> {
+        lock(&self.conn).execute(
+            "UPDATE oauthcli_request_tokens SET user = ?2, verifier = ?3
+            WHERE token = ?1 AND expires_at > ?4 AND verifier IS NULL",
+            params![token, user, verifier, to_sql_time(now)]).map(|x| x > 0)
+    }
+
+    fn take_request_token(&self, token: &str, now: u64) -> rusqlite::Result<Option<RequestTokenRecord>> {
+        let record = try!(lock(&self.conn).query_row(
+            "DELETE FROM oauthcli_request_tokens WHERE token = ?1
+            RETURNING token, secret, consumer_key, callback, user, verifier, expires_at",
+            [token], request_token_record).optional());
+        Ok(record.filter(|x| now < x.expires_at))
+    }
+
+    fn remove_expired_request_tokens(&self, now: u64) -> rusqlite::Result<()> {
+        try!(lock(&self.conn).execute("DELETE FROM oauthcli_request_tokens WHERE expires_at <= ?1", [to_sql_time(now)]));
+        Ok(())
+    }
+}
+
+impl AccessTokenStore for SqliteStore {
+    type Error = rusqlite::Error;
+
+    fn insert_access_token(&self, record: &AccessTokenRecord) -> rusqlite::Result<()> {
+        try!(lock(&self.conn).execute(
+            "INSERT OR REPLACE INTO oauthcli_access_tokens (token, secret, consumer_key, user) VALUES (?1, ?2, ?3, ?4)",
+            params![record.credentials.token, record.credentials.secret.expose(), record.consumer_key, record.user]));
+        Ok(())
+    }
+
+    fn access_token(&self, token: &str) -> rusqlite::Result<Option<AccessTokenRecord>> {
+        lock(&self.conn).query_row(
+            "SELECT token, secret, consumer_key, user FROM oauthcli_access_tokens WHERE token = ?1", [token],
+            |row| Ok(AccessTokenRecord {
+                consumer_key: try!(row.get(2)),
+                credentials: TokenCredentials::new(try!(row.get::<_, String>(0)), try!(row.get::<_, String>(1))),
+                user: try!(row.get(3))
+            })).optional()
+    }
+
+    fn revoke_access_token(&self, token: &str) -> rusqlite::Result<bool> {
+        lock(&self.conn).execute("DELETE FROM oauthcli_access_tokens WHERE token = ?1", [token]).map(|x| x > 0)
+    }
+}
+
+impl NonceStore for SqliteStore {
+    type Error = rusqlite::Error;
+
+    fn use_nonce(&self, consumer_key: &str, token: Option<&str>, timestamp: u64, nonce: &str) -> rusqlite::Result<bool> {
+        // NULL is not unique in the primary key, so the absent token is an empty string
+        lock(&self.conn).execute(
+            "INSERT OR IGNORE INTO oauthcli_nonces (consumer_key, token, timestamp, nonce) VALUES (?1, ?2, ?3, ?4)",
+            params![consumer_key, token.unwrap_or(""), to_sql_time(timestamp), nonce]).map(|x| x > 0)
+    }
+
+    fn remove_nonces_before(&self, timestamp: u64) -> rusqlite::Result<()> {
+        try!(lock(&self.conn).execute("DELETE FROM oauthcli_nonces WHERE timestamp < ?1", [to_sql_time(timestamp)]));
+        Ok(())
+    }
+}
@@ -0,0 +1,223 @@
+//! Stores of request token secrets between the redirect to the Service Provider and the callback.
+//!
+//! # Example
+//! ```
+//! # extern crate oauthcli;
+//! # extern crate url;
+//! # use oauthcli::*;
+//! # use oauthcli::store::*;
+//! # fn main() {
+//! let store = MemoryTokenStore::new(600);
+//! let res = OAuthTokenResponse::from_body(
+//!     "oauth_token=hh5s93j4hdidpola&oauth_token_secret=hdhd0244k9j7ao03&oauth_callback_confirmed=true"
+//! ).unwrap();
+//! store.insert(&res.token, &res.token_secret, 1500000000).unwrap();
+//!
+//! // In the callback handler
+//! let callback = url::Url::parse(
+//!     "http://printer.example.com/ready?oauth_token=hh5s93j4hdidpola&oauth_verifier=hfdp7dh39dks9884"
+//! ).unwrap();
+//! let callback = OAuthCallback::from_url(&callback).unwrap();
+//! let secret = store.take(callback.token(), 1500000060).unwrap().unwrap();
+//! assert_eq!(secret.expose(), "hdhd0244k9j7ao03");
+//! assert!(store.take(callback.token(), 1500000060).unwrap().is_none());
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::{Mutex, MutexGuard};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use fs2::FileExt;
+use url::percent_encoding::percent_decode;
+use super::{TokenSecret, percent_encode};
+
+/// Maps `oauth_token` of a request token to its secret until the secret is taken or expires.
+///
+/// The times are in seconds since the Unix epoch.
+pub trait PendingTokenStore {
+    type Error;
+
+    /// Saves `secret` of `token` issued at `now`.
+    fn insert(&self, token: &str, secret: &TokenSecret, now: u64) -> Result<(), Self::Error>;
+
+    /// Removes and returns the secret of `token` unless it has expired at `now`.
+    /// The secret is returned only once.
+    fn take(&self, token: &str, now: u64) -> Result<Option<TokenSecret<'static>>, Self::Error>;
+
+    /// Removes the secrets which have expired at `now`.
+    fn remove_expired(&self, now: u64) -> Result<(), Self::Error>;
+}
+
+type Entries = HashMap<String, (TokenSecret<'static>, u64)>;
+
+pub(crate) fn lock<T>(x: &Mutex<T>) -> MutexGuard<T> {
+    // The entries are consistent even if another thread panicked
+    x.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn take_entry(entries: &mut Entries, token: &str, now: u64) -> Option<TokenSecret<'static>> {
+    match entries.remove(token) {
+        Some((secret, expires_at)) if now < expires_at => Some(secret),
+        _ => None
+    }
+}
+
+/// `PendingTokenStore` in memory, which is shared by the threads of a process.
+#[derive(Debug)]
+pub struct MemoryTokenStore {
+    ttl: u64,
+    entries: Mutex<Entries>
+}
+
+impl MemoryTokenStore {
+    /// Creates a store whose secrets expire `ttl` seconds after they are inserted.
+    pub fn new(ttl: u64) -> MemoryTokenStore {
+        MemoryTokenStore { ttl: ttl, entries: Mutex::new(HashMap::new()) }
+    }
+}
+
+impl PendingTokenStore for MemoryTokenStore {
+    type Error = Infallible;
+
+    fn insert(&self, token: &str, secret: &TokenSecret, now: u64) -> Result<(), Infallible> {
+        let expires_at = now.saturating_add(self.ttl);
+        lock(&self.entries).insert(token.to_owned(), (secret.clone().into_owned(), expires_at));
+        Ok(())
+    }
+
+    fn take(&self, token: &str, now: u64) -> Result<Option<TokenSecret<'static>>, Infallible> {
+        Ok(take_entry(&mut lock(&self.entries), token, now))
+    }
+
+    fn remove_expired(&self, now: u64) -> Result<(), Infallible> {
+        lock(&self.entries).retain(|_, &mut (_, expires_at)| now < expires_at);
+        Ok(())
+    }
+}
+
+/// `PendingTokenStore` in a file, which survives restarts of the process.
+///
+/// Each line of the file is percent-encoded `oauth_token`, `oauth_token_secret` and the expiry time.
+/// The file is rewritten on every change, and is readable only by the owner on Unix.
+///
+/// Every operation reads, modifies and replaces the file while holding an exclusive OS lock of `<path>.lock`,
+/// so stores of the same path are consistent across threads and processes.
+/// The lock is advisory, so other programs writing the file directly are not excluded.
+#[derive(Debug)]
+pub struct FileTokenStore {
+    path: PathBuf,
+    ttl: u64
+}
+
+impl FileTokenStore {
+    /// Creates a store in `path` whose secrets expire `ttl` seconds after they are inserted.
+    /// The file is created on the first insertion.
+    pub fn new<P: Into<PathBuf>>(path: P, ttl: u64) -> FileTokenStore {
+        FileTokenStore { path: path.into(), ttl: ttl }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Locks `<path>.lock` until the returned file is dropped.
+    fn lock(&self) -> io::Result<File> {
+        let mut lock_path = self.path.clone().into_os_string();
+        lock_path.push(".lock");
+
+        let file = try!(OpenOptions::new().write(true).create(true).open(&lock_path));
+        try!(file.lock_exclusive());
+        Ok(file)
+    }
+
+    fn read(&self) -> io::Result<Entries> {
+        fn decode(s: &str) -> io::Result<String> {
+            percent_decode(s.as_bytes()).decode_utf8()
+                .map(|x| x.into_owned())
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        }
+
+        let file = match File::open(&self.path) {
+            Ok(x) => x,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(HashMap::new()),
+            Err(e) => return Err(e)
+        };
+
+        let mut entries = HashMap::new();
+        for line in BufReader::new(file).lines() {
+            let line = try!(line);
+            let mut fields = line.split(' ');
+            match (fields.next(), fields.next(), fields.next().and_then(|x| x.parse().ok())) {
+                (Some(token), Some(secret), Some(expires_at)) => {
+                    entries.insert(try!(decode(token)), (TokenSecret::new(try!(decode(secret))), expires_at));
+                },
+                _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "Malformed pending token store"))
+            }
+        }
+
+        Ok(entries)
+    }
+
+    fn write(&self, entries: &Entries) -> io::Result<()> {
+        // Unique in the processes sharing the file
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let mut tmp_path = self.path.clone().into_os_string();
+        tmp_path.push(format!(".{}.{}.tmp", process::id(), COUNTER.fetch_add(1, Ordering::Relaxed)));
+
+        let result = write_entries(&tmp_path, entries).and_then(|_| fs::rename(&tmp_path, &self.path));
+        if result.is_err() {
+            let _ = fs::remove_file(&tmp_path);
+        }
+        result
+    }
+}
+
+fn write_entries<P: AsRef<Path>>(path: P, entries: &Entries) -> io::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut file = try!(options.open(path));
+    for (token, &(ref secret, expires_at)) in entries {
+        try!(writeln!(file, "{} {} {}",
+            percent_encode(token), percent_encode(secret.expose()), expires_at));
+    }
+    file.sync_all()
+}
+
+impl PendingTokenStore for FileTokenStore {
+    type Error = io::Error;
+
+    fn insert(&self, token: &str, secret: &TokenSecret, now: u64) -> io::Result<()> {
+        let _lock = try!(self.lock());
+        let mut entries = try!(self.read());
+        entries.insert(token.to_owned(), (secret.clone().into_owned(), now.saturating_add(self.ttl)));
+        self.write(&entries)
+    }
+
+    fn take(&self, token: &str, now: u64) -> io::Result<Option<TokenSecret<'static>>> {
+        let _lock = try!(self.lock());
+        let mut entries = try!(self.read());
+        if !entries.contains_key(token) { return Ok(None); }
+        let secret = take_entry(&mut entries, token, now);
+        try!(self.write(&entries));
+        Ok(secret)
+    }
+
+    fn remove_expired(&self, now: u64) -> io::Result<()> {
+        let _lock = try!(self.lock());
+        let mut entries = try!(self.read());
+        entries.retain(|_, &mut (_, expires_at)| now < expires_at);
+        self.write(&entries)
+    }
+}
@@ -178,3 +178,1163 @@ fn example_photos() {
         oauth_signature=\"MdpQcU8iPSUjWoN%2FUDMsK2sui9I%3D\""
     );
 }
+
+#[test]
+fn problem_report_test() {
+    let report = OAuthProblemReport::from_www_authenticate(
+        "OAuth realm=\"Photos\", oauth_problem=\"parameter_absent\", \
+         oauth_parameters_absent=\"oauth_nonce%26oauth_timestamp\", \
+         oauth_problem_advice=\"Nonce%20and%20timestamp%20are%20required\"").unwrap();
+    assert_eq!(report.problem, OAuthProblem::ParameterAbsent);
+    assert_eq!(report.parameters_absent, vec!["oauth_nonce", "oauth_timestamp"]);
+    assert_eq!(report.problem_advice, Some("Nonce and timestamp are required".to_owned()));
+
+    // realm is a quoted-string
+    let challenge: OAuthChallenge =
+        "OAuth realm=\"https://api.twitter.com\", oauth_problem=\"token_rejected\"".parse().unwrap();
+    assert_eq!(challenge.realm(), Some("https://api.twitter.com"));
+    assert_eq!(challenge.problem_report().unwrap().problem, OAuthProblem::TokenRejected);
+    let challenge: OAuthChallenge =
+        "OAuth realm=\"My Photos, \\\"Family\\\"\",oauth_problem=\"nonce_used\"".parse().unwrap();
+    assert_eq!(challenge.realm(), Some("My Photos, \"Family\""));
+    assert_eq!(challenge.problem_report().unwrap().problem, OAuthProblem::NonceUsed);
+    assert_eq!(
+        OAuthProblemReport::from_www_authenticate("OAuth oauth_problem=\"token rejected\""),
+        Err(ParseOAuthProblemError::HeaderError(ParseOAuthAuthorizationHeaderError::EscapeError))
+    );
+
+    let report = OAuthProblemReport::from_body(
+        "oauth_problem=version_rejected&oauth_acceptable_versions=1.0-1.0").unwrap();
+    assert_eq!(report.problem, OAuthProblem::VersionRejected);
+    assert_eq!(report.acceptable_versions, Some(("1.0".to_owned(), "1.0".to_owned())));
+
+    assert_eq!(
+        OAuthProblemReport::from_body("oauth_problem=vendor_specific").unwrap().problem,
+        OAuthProblem::Other("vendor_specific".to_owned())
+    );
+    assert_eq!(
+        OAuthProblemReport::from_body("oauth_token=foo"),
+        Err(ParseOAuthProblemError::ProblemAbsent)
+    );
+    assert_eq!(
+        OAuthProblemReport::from_body("oauth_problem=timestamp_refused&oauth_acceptable_timestamps=abc"),
+        Err(ParseOAuthProblemError::InvalidParameter("oauth_acceptable_timestamps"))
+    );
+}
+
+#[test]
+fn challenge_test() {
+    let mut report = OAuthProblemReport::new(OAuthProblem::ParameterAbsent);
+    report.parameters_absent = vec!["oauth_nonce".to_owned(), "oauth_timestamp".to_owned()];
+    report.problem_advice = Some("Nonce & timestamp".to_owned());
+
+    let challenge = OAuthChallengeBuilder::new()
+        .realm("Photos")
+        .problem_report(report.clone())
+        .finish();
+
+    assert_eq!(
+        challenge.to_string(),
+        "OAuth realm=\"Photos\",\
+        oauth_problem=\"parameter_absent\",\
+        oauth_parameters_absent=\"oauth_nonce%26oauth_timestamp\",\
+        oauth_problem_advice=\"Nonce%20%26%20timestamp\""
+    );
+    assert_eq!(challenge.to_string().parse::<OAuthChallenge>().unwrap(), challenge);
+    assert_eq!(OAuthProblemReport::from_www_authenticate(&challenge.to_string()).unwrap(), report);
+    assert_eq!(OAuthProblemReport::from_body(&report.to_body()).unwrap(), report);
+
+    let challenge = OAuthChallengeBuilder::new()
+        .realm("My \"Photos\" at https://photos.example.net")
+        .problem(OAuthProblem::TokenExpired)
+        .finish();
+    assert_eq!(
+        challenge.to_string(),
+        "OAuth realm=\"My \\\"Photos\\\" at https://photos.example.net\",oauth_problem=\"token_expired\""
+    );
+    assert_eq!(challenge.to_string().parse::<OAuthChallenge>().unwrap(), challenge);
+
+    let url = Url::parse("https://photos.example.net/photos").unwrap();
+    let header = OAuthAuthorizationHeaderBuilder::new("GET", &url, "consumer", "secret", SignatureMethod::HmacSha1)
+        .realm("https://photos.example.net/")
+        .finish();
+    assert!(header.auth_param().starts_with("realm=\"https://photos.example.net/\","));
+    assert_eq!(SignedRequest::from_header(&header).unwrap().realm(), Some("https://photos.example.net/"));
+
+    let challenge = OAuthChallengeBuilder::new().finish();
+    assert_eq!(challenge.to_string(), "OAuth");
+    assert_eq!(challenge.to_string().parse::<OAuthChallenge>().unwrap(), challenge);
+}
+
+#[test]
+fn token_response_test() {
+    let res = OAuthTokenResponse::from_body(
+        "oauth_token=nnch734d00sl2jdk&oauth_token_secret=pfkkdhi9sl3r4s00&user_id=6253282&screen_name=twitterapi"
+    ).unwrap();
+    assert_eq!(res.token, "nnch734d00sl2jdk");
+    assert_eq!(res.token_secret.expose(), "pfkkdhi9sl3r4s00");
+    assert_eq!(res.callback_confirmed, None);
+    assert_eq!(res.parameter("screen_name"), Some("twitterapi"));
+
+    assert_eq!(
+        OAuthTokenResponse::from_body("oauth_token=a"),
+        Err(ParseOAuthTokenResponseError::ParameterAbsent("oauth_token_secret"))
+    );
+    assert_eq!(
+        OAuthTokenResponse::from_body("oauth_token=a&oauth_token_secret=b&oauth_expires_in=x"),
+        Err(ParseOAuthTokenResponseError::InvalidParameter("oauth_expires_in"))
+    );
+}
+
+#[test]
+fn session_test() {
+    let res = OAuthTokenResponse::from_body(
+        "oauth_token=token&oauth_token_secret=secret&oauth_session_handle=handle\
+        &oauth_expires_in=3600&oauth_authorization_expires_in=86400"
+    ).unwrap();
+    let mut session = OAuthSession::from_token_response(&res, 1000).unwrap();
+    assert_eq!(session.expires_at, Some(4600));
+    assert_eq!(session.authorization_expires_at, Some(87400));
+    assert!(!session.needs_refresh(1000, 300));
+    assert!(session.needs_refresh(4400, 300));
+    assert!(!session.needs_refresh(87400, 300));
+
+    let url = Url::parse("https://api.example.com/oauth/get_token").unwrap();
+    let header = session.refresh_request(&url, "consumer", "consumer_secret", SignatureMethod::Plaintext)
+        .timestamp(4400)
+        .nonce("nonce")
+        .include_version(false)
+        .finish();
+    assert_eq!(
+        header.to_string(),
+        "OAuth oauth_consumer_key=\"consumer\",\
+        oauth_token=\"token\",\
+        oauth_signature_method=\"PLAINTEXT\",\
+        oauth_timestamp=\"4400\",\
+        oauth_nonce=\"nonce\",\
+        oauth_session_handle=\"handle\",\
+        oauth_signature=\"consumer_secret%26secret\""
+    );
+
+    let res = OAuthTokenResponse::from_body("oauth_token=token2&oauth_token_secret=secret2&oauth_expires_in=3600").unwrap();
+    session.update(&res, 4400);
+    assert_eq!(session.token, "token2");
+    assert_eq!(session.session_handle, "handle");
+    assert_eq!(session.expires_at, Some(8000));
+    assert!(OAuthSession::from_token_response(&res, 4400).is_none());
+}
+
+// https://developer.twitter.com/en/docs/basics/authentication/overview/xauth
+
+#[test]
+fn twitter_xauth() {
+    let url = Url::parse(twitter::ACCESS_TOKEN_URL).unwrap();
+    let req = twitter::xauth(
+        OAuthAuthorizationHeaderBuilder::new("POST", &url, "JvyS7DO2qd6NNTsXJ4E7zA", "9z6157pUbOBqtbm0A0q4r29Y2EYzIHlUwbF4Cl9c", SignatureMethod::HmacSha1)
+            .timestamp(1284565601)
+            .nonce("6AN2dKRzxyGhmIXUKSmp1JcB4pckM8rD3frKMTmVAo"),
+        "oauth_test_exec", "twitter-xauth");
+
+    assert_eq!(
+        req.authorization.to_string(),
+        "OAuth oauth_consumer_key=\"JvyS7DO2qd6NNTsXJ4E7zA\",\
+        oauth_signature_method=\"HMAC-SHA1\",\
+        oauth_timestamp=\"1284565601\",\
+        oauth_nonce=\"6AN2dKRzxyGhmIXUKSmp1JcB4pckM8rD3frKMTmVAo\",\
+        oauth_version=\"1.0\",\
+        oauth_signature=\"1L1oXQmawZAkQ47FHLwcOV%2Bkjwc%3D\""
+    );
+    assert_eq!(req.body, "x_auth_mode=client_auth&x_auth_password=twitter-xauth&x_auth_username=oauth_test_exec");
+
+    // The builder is not modified, so it can be reused
+    let mut builder = OAuthAuthorizationHeaderBuilder::new("POST", &url, "JvyS7DO2qd6NNTsXJ4E7zA", "9z6157pUbOBqtbm0A0q4r29Y2EYzIHlUwbF4Cl9c", SignatureMethod::HmacSha1);
+    builder.timestamp(1284565601).nonce("6AN2dKRzxyGhmIXUKSmp1JcB4pckM8rD3frKMTmVAo");
+    let first = twitter::xauth(&builder, "oauth_test_exec", "twitter-xauth");
+    let second = twitter::xauth(&builder, "oauth_test_exec", "twitter-xauth");
+    assert_eq!(first.authorization.to_string(), req.authorization.to_string());
+    assert_eq!(second.authorization.to_string(), req.authorization.to_string());
+    assert_eq!(
+        builder.finish().to_string(),
+        OAuthAuthorizationHeaderBuilder::new("POST", &url, "JvyS7DO2qd6NNTsXJ4E7zA", "9z6157pUbOBqtbm0A0q4r29Y2EYzIHlUwbF4Cl9c", SignatureMethod::HmacSha1)
+            .timestamp(1284565601)
+            .nonce("6AN2dKRzxyGhmIXUKSmp1JcB4pckM8rD3frKMTmVAo")
+            .finish()
+            .to_string()
+    );
+
+    let token = twitter::AccessToken::from_body(
+        "oauth_token=819797-torCkTs0XK7H2Y2i1ee5iofqkMC4p7aayeEXRTmlw\
+        &oauth_token_secret=SpuaLmgFNYfRdyfXVvIRRKHDGGkbhC5GMexTRGHgvs\
+        &user_id=819797&screen_name=episod&x_auth_expires=0"
+    ).unwrap();
+    assert_eq!(token.token, "819797-torCkTs0XK7H2Y2i1ee5iofqkMC4p7aayeEXRTmlw");
+    assert_eq!(token.user_id, Some("819797".to_owned()));
+    assert_eq!(token.screen_name, Some("episod".to_owned()));
+}
+
+#[test]
+fn echo_test() {
+    use std::cell::RefCell;
+    use echo::*;
+
+    struct MockTransport(RefCell<Vec<(String, String)>>);
+
+    impl EchoTransport for MockTransport {
+        type Error = ();
+
+        fn get(&self, url: &Url, authorization: &str) -> Result<EchoResponse, ()> {
+            self.0.borrow_mut().push((url.to_string(), authorization.to_owned()));
+            Ok(EchoResponse {
+                status: if authorization.contains("oauth_token=\"valid\"") { 200 } else { 401 },
+                body: b"{}".to_vec()
+            })
+        }
+    }
+
+    let url = Url::parse("https://api.twitter.com/1.1/account/verify_credentials.json").unwrap();
+    let echo = headers(
+        OAuthAuthorizationHeaderBuilder::new("GET", &url, "consumer", "secret", SignatureMethod::HmacSha1)
+            .token("valid", "secret"));
+    assert_eq!(echo.service_provider, url.as_str());
+
+    let mut verifier = EchoVerifier::new();
+    verifier.allow_service_provider(url.clone());
+    let transport = MockTransport(RefCell::new(Vec::new()));
+
+    let res = verifier.verify(&transport, &echo.service_provider, &echo.verify_credentials_authorization.to_string());
+    assert_eq!(res.unwrap().body, b"{}");
+    assert_eq!(
+        transport.0.borrow()[0],
+        (url.to_string(), echo.verify_credentials_authorization.to_string())
+    );
+
+    match verifier.verify(&transport, "https://evil.example.com/", &echo.verify_credentials_authorization.to_string()) {
+        Err(EchoError::ServiceProviderRejected) => (),
+        x => panic!("{:?}", x)
+    }
+    match verifier.verify(&transport, url.as_str(), "oauth_token=\"other\"") {
+        Err(EchoError::Rejected(ref res)) if res.status == 401 => (),
+        x => panic!("{:?}", x)
+    }
+    match verifier.verify(&transport, url.as_str(), "invalid") {
+        Err(EchoError::InvalidAuthorization(ParseOAuthAuthorizationHeaderError::FormatError)) => (),
+        x => panic!("{:?}", x)
+    }
+    assert_eq!(transport.0.borrow().len(), 2);
+}
+
+#[test]
+fn twitter_reverse_auth() {
+    let request_token_url = Url::parse(twitter::REQUEST_TOKEN_URL).unwrap();
+    let req = twitter::reverse_auth_request(
+        OAuthAuthorizationHeaderBuilder::new("POST", &request_token_url, "server_key", "server_secret", SignatureMethod::Plaintext)
+            .timestamp(1500000000)
+            .nonce("nonce")
+            .include_version(false));
+    assert_eq!(req.body, "x_auth_mode=reverse_auth");
+    assert_eq!(
+        req.authorization.to_string(),
+        "OAuth oauth_consumer_key=\"server_key\",\
+        oauth_signature_method=\"PLAINTEXT\",\
+        oauth_timestamp=\"1500000000\",\
+        oauth_nonce=\"nonce\",\
+        oauth_signature=\"server_secret%26\""
+    );
+
+    let params = twitter::parse_reverse_auth_parameters(
+        "OAuth oauth_timestamp=\"1500000000\", oauth_signature=\"abc%3D\", oauth_consumer_key=\"server_key\"").unwrap();
+    assert_eq!(
+        params.to_string(),
+        "OAuth oauth_timestamp=\"1500000000\", oauth_signature=\"abc%3D\", oauth_consumer_key=\"server_key\""
+    );
+
+    let access_token_url = Url::parse(twitter::ACCESS_TOKEN_URL).unwrap();
+    let req = twitter::reverse_auth_access_token(
+        OAuthAuthorizationHeaderBuilder::new("POST", &access_token_url, "device_key", "device_secret", SignatureMethod::HmacSha1)
+            .token("device_token", "device_token_secret"),
+        "server_key", &params);
+    assert_eq!(
+        req.body,
+        "x_reverse_auth_parameters=OAuth%20oauth_timestamp%3D%221500000000%22%2C%20\
+        oauth_signature%3D%22abc%253D%22%2C%20oauth_consumer_key%3D%22server_key%22\
+        &x_reverse_auth_target=server_key"
+    );
+}
+
+#[test]
+fn two_legged_test() {
+    let url = Url::parse("http://example.com/feeds/default?max-results=10").unwrap();
+    let mut builder = OAuthAuthorizationHeaderBuilder::new("GET", &url, "consumer", "consumer_secret", SignatureMethod::HmacSha1);
+    builder.requestor_id("someone@example.com");
+    assert_eq!(builder.xoauth_parameter("displayname", "John Doe").err(), Some(HeaderParameterError::NotExtension));
+    let header = builder
+        .requestor_id("john.doe@example.com")
+        .xoauth_parameter("xoauth_displayname", "John Doe").unwrap()
+        .timestamp(137131200)
+        .nonce("nonce")
+        .finish();
+    assert_eq!(builder.xoauth_parameter("xoauth_displayname", "Jane Doe").err(), Some(HeaderParameterError::Duplicated));
+
+    assert_eq!(
+        header.to_string(),
+        "OAuth oauth_consumer_key=\"consumer\",\
+        oauth_signature_method=\"HMAC-SHA1\",\
+        oauth_timestamp=\"137131200\",\
+        oauth_nonce=\"nonce\",\
+        xoauth_requestor_id=\"john.doe%40example.com\",\
+        xoauth_displayname=\"John%20Doe\",\
+        oauth_version=\"1.0\",\
+        oauth_signature=\"Riwfx4YRZF8jInne%2Btij17cVJqI%3D\""
+    );
+
+    let req = SignedRequest::from_header(&header).unwrap();
+    assert_eq!(req.consumer_key(), "consumer");
+    assert_eq!(req.token(), None);
+    assert_eq!(req.requestor_id(), Some("john.doe@example.com"));
+    assert_eq!(req.parameter("xoauth_displayname"), Some("John Doe"));
+    assert_eq!(req.timestamp(), Some(137131200));
+
+    let no_params = Vec::<(&str, &str)>::new();
+    assert_eq!(req.verify("GET", &url, no_params.clone(), "consumer_secret", None), Ok(()));
+    assert_eq!(req.verify("GET", &url, no_params.clone(), "wrong", None), Err(VerifyError::SignatureInvalid));
+    assert_eq!(req.verify("GET", &url, vec![("a", "b")], "consumer_secret", None), Err(VerifyError::SignatureInvalid));
+
+    // A tampered requestor ID does not match the signature
+    let tampered: OAuthAuthorizationHeader = header.auth_param()
+        .replace("john.doe%40example.com", "jane.doe%40example.com").parse().unwrap();
+    assert_eq!(
+        SignedRequest::from_header(&tampered).unwrap().verify("GET", &url, no_params, "consumer_secret", None),
+        Err(VerifyError::SignatureInvalid)
+    );
+}
+
+#[test]
+fn verify_test() {
+    fn f(s: &str) -> Result<SignedRequest, VerifyError> {
+        SignedRequest::from_header(&s.parse().unwrap())
+    }
+
+    let url = Url::parse("http://photos.example.net/photos?file=vacation.jpg&size=original").unwrap();
+    let req = f("OAuth realm=\"Photos\",\
+        oauth_consumer_key=\"dpf43f3p2l4k3l03\",\
+        oauth_token=\"nnch734d00sl2jdk\",\
+        oauth_signature_method=\"HMAC-SHA1\",\
+        oauth_timestamp=\"137131202\",\
+        oauth_nonce=\"chapoH\",\
+        oauth_signature=\"MdpQcU8iPSUjWoN%2FUDMsK2sui9I%3D\"").unwrap();
+    assert_eq!(req.realm(), Some("Photos"));
+    assert_eq!(req.token(), Some("nnch734d00sl2jdk"));
+    assert_eq!(
+        req.verify("GET", &url, Vec::<(&str, &str)>::new(), "kd94hf93k423kf44", Some("pfkkdhi9sl3r4s00")),
+        Ok(())
+    );
+
+    assert_eq!(f("oauth_signature_method=\"PLAINTEXT\",oauth_signature=\"a%26\"").unwrap_err(),
+        VerifyError::ParameterAbsent("oauth_consumer_key"));
+    assert!(f("oauth_consumer_key=\"a\",oauth_signature_method=\"PLAINTEXT\",oauth_signature=\"a%26\"").is_ok());
+    assert_eq!(f("oauth_consumer_key=\"a\",oauth_signature_method=\"HMAC-SHA1\",oauth_signature=\"a\"").unwrap_err(),
+        VerifyError::ParameterAbsent("oauth_timestamp"));
+    assert_eq!(f("oauth_consumer_key=\"a\",oauth_signature_method=\"RSA-SHA1\",oauth_signature=\"a\"").unwrap_err(),
+        VerifyError::SignatureMethodRejected);
+    assert_eq!(f("oauth_consumer_key=\"a\",oauth_consumer_key=\"b\"").unwrap_err(),
+        VerifyError::ParameterDuplicated);
+    assert_eq!(f("oauth_consumer_key=\"a\",oauth_version=\"2.0\"").unwrap_err(),
+        VerifyError::VersionRejected);
+}
+
+#[test]
+fn header_parameter_test() {
+    let url = Url::parse("https://photos.example.net/photos").unwrap();
+    let mut builder = OAuthAuthorizationHeaderBuilder::new("POST", &url, "consumer", "consumer_secret", SignatureMethod::HmacSha1);
+    builder
+        .token("token", "token_secret")
+        .timestamp(137131200)
+        .nonce("nonce")
+        .include_version(false);
+
+    builder.header_parameter("oauth_body_hash", "Lve95gjOVATpfV8EL5X4nxwjKHE=").unwrap();
+    builder.header_parameter("vendor_param", "a b").unwrap();
+
+    assert_eq!(builder.header_parameter("oauth_signature", "x").err(), Some(HeaderParameterError::Reserved));
+    assert_eq!(builder.header_parameter("realm", "x").err(), Some(HeaderParameterError::Reserved));
+    assert_eq!(builder.header_parameter("", "x").err(), Some(HeaderParameterError::Reserved));
+    assert_eq!(builder.header_parameter("vendor_param", "x").err(), Some(HeaderParameterError::Duplicated));
+
+    let header = builder.finish();
+    assert_eq!(
+        header.to_string(),
+        "OAuth oauth_consumer_key=\"consumer\",\
+        oauth_token=\"token\",\
+        oauth_signature_method=\"HMAC-SHA1\",\
+        oauth_timestamp=\"137131200\",\
+        oauth_nonce=\"nonce\",\
+        oauth_body_hash=\"Lve95gjOVATpfV8EL5X4nxwjKHE%3D\",\
+        vendor_param=\"a%20b\",\
+        oauth_signature=\"qLmTkQHaIOyXuUG6JnHMf7Lm5XA%3D\""
+    );
+
+    let req = SignedRequest::from_header(&header).unwrap();
+    assert_eq!(req.parameter("oauth_body_hash"), Some("Lve95gjOVATpfV8EL5X4nxwjKHE="));
+    assert_eq!(req.verify("POST", &url, Vec::<(&str, &str)>::new(), "consumer_secret", Some("token_secret")), Ok(()));
+}
+
+#[test]
+fn multipart_test() {
+    use multipart::*;
+
+    let url = Url::parse("https://upload.twitter.com/1.1/media/upload.json?media_category=tweet_image").unwrap();
+    let mut form = MultipartForm::with_boundary("boundary").unwrap();
+    form.text("additional_owners", "12345")
+        .file("media", "a\"b.png", "image/png", &b"\x89PNG"[..]).unwrap();
+
+    let mut builder = OAuthAuthorizationHeaderBuilder::new("POST", &url, "consumer", "consumer_secret", SignatureMethod::HmacSha1);
+    builder
+        .token("token", "token_secret")
+        .timestamp(137131200)
+        .nonce("nonce");
+    let req = sign_for_twitter(&builder, &form);
+
+    // Same as the request without the body
+    assert_eq!(req.authorization.to_string(), builder.finish_for_twitter().to_string());
+    assert_eq!(req.content_type, "multipart/form-data; boundary=boundary");
+    assert_eq!(
+        req.body,
+        &b"--boundary\r\n\
+        Content-Disposition: form-data; name=\"additional_owners\"\r\n\
+        \r\n\
+        12345\r\n\
+        --boundary\r\n\
+        Content-Disposition: form-data; name=\"media\"; filename=\"a%22b.png\"\r\n\
+        Content-Type: image/png\r\n\
+        \r\n\
+        \x89PNG\r\n\
+        --boundary--\r\n"[..]
+    );
+
+    assert_eq!(
+        form.file("media", "b.png", "image/png\r\nX-Injected: 1", &b""[..]).err(),
+        Some(MultipartError::InvalidContentType)
+    );
+    assert_eq!(MultipartForm::with_boundary("").err(), Some(MultipartError::InvalidBoundary));
+    assert_eq!(MultipartForm::with_boundary("a\r\nb").err(), Some(MultipartError::InvalidBoundary));
+    assert_eq!(MultipartForm::with_boundary(&"a"[..].repeat(71)).err(), Some(MultipartError::InvalidBoundary));
+}
+
+#[test]
+fn multipart_with_request_parameters() {
+    let url = Url::parse("https://upload.twitter.com/1.1/media/upload.json").unwrap();
+    let form = multipart::MultipartForm::new();
+    let mut builder = OAuthAuthorizationHeaderBuilder::new("POST", &url, "consumer", "consumer_secret", SignatureMethod::HmacSha1);
+    builder.timestamp(1500000000).nonce("nonce");
+    let expected = multipart::sign(&builder, &form).authorization;
+
+    // The request parameters are ignored
+    builder.request_parameters(vec![("media_data", "AAAA")]);
+    assert_eq!(multipart::sign(&builder, &form).authorization.auth_param(), expected.auth_param());
+}
+
+#[test]
+fn byte_parameters_test() {
+    // "テスト" in Shift_JIS
+    let body = b"name=%83e%83X%83g";
+    let params = decode_form_body(body);
+    assert_eq!(params, vec![(b"name".to_vec(), vec![0x83, 0x65, 0x83, 0x58, 0x83, 0x67])]);
+
+    let url = Url::parse("http://legacy.example.jp/api").unwrap();
+    let header = OAuthAuthorizationHeaderBuilder::new("POST", &url, "consumer", "consumer_secret", SignatureMethod::HmacSha1)
+        .request_byte_parameters(params.iter().map(|&(ref k, ref v)| (&k[..], &v[..])))
+        .timestamp(137131200)
+        .nonce("nonce")
+        .include_version(false)
+        .finish();
+
+    assert_eq!(
+        header.to_string(),
+        "OAuth oauth_consumer_key=\"consumer\",\
+        oauth_signature_method=\"HMAC-SHA1\",\
+        oauth_timestamp=\"137131200\",\
+        oauth_nonce=\"nonce\",\
+        oauth_signature=\"9AB%2BNIVMCXSWnioHwZ%2F81RXXgv4%3D\""
+    );
+
+    let req = SignedRequest::from_header(&header).unwrap();
+    assert_eq!(req.verify_bytes("POST", &url, params.clone(), "consumer_secret", None), Ok(()));
+
+    let header = OAuthAuthorizationHeaderBuilder::new("POST", &url, "consumer", "consumer_secret", SignatureMethod::HmacSha1)
+        .request_byte_parameters(params.iter().map(|&(ref k, ref v)| (&k[..], &v[..])))
+        .finish_for_twitter();
+    let req = SignedRequest::from_header(&header).unwrap();
+    assert_eq!(req.verify_with(VerifyOptions::new().for_twitter(true), "POST", &url, params, "consumer_secret", None), Ok(()));
+
+    assert_eq!(
+        decode_form_body(b"a+b=c%2Bd&&e"),
+        vec![(b"a b".to_vec(), b"c+d".to_vec()), (b"e".to_vec(), Vec::new())]
+    );
+}
+
+#[test]
+fn finish_with_body_test() {
+    let url = Url::parse("http://www.example.com/resource").unwrap();
+    let mut builder = OAuthAuthorizationHeaderBuilder::new("PUT", &url, "consumer", "consumer_secret", SignatureMethod::HmacSha1);
+    builder
+        .token("token", "token_secret")
+        .timestamp(137131200)
+        .nonce("nonce")
+        .include_version(false);
+
+    assert_eq!(
+        builder.finish_with_body(Some("text/plain"), b"Hello World!").unwrap().to_string(),
+        "OAuth oauth_consumer_key=\"consumer\",\
+        oauth_token=\"token\",\
+        oauth_signature_method=\"HMAC-SHA1\",\
+        oauth_timestamp=\"137131200\",\
+        oauth_nonce=\"nonce\",\
+        oauth_body_hash=\"Lve95gjOVATpfV8EL5X4nxwjKHE%3D\",\
+        oauth_signature=\"DEv5LNmN%2Fj9%2BNwZoN1%2Foj2Mif3M%3D\""
+    );
+
+    // The form-encoded body is signed as the request parameters
+    let form = builder.finish_with_body(Some("application/x-www-form-urlencoded; charset=utf-8"), b"status=a+b%21").unwrap();
+    builder.request_parameters(vec![("status", "a b!")]);
+    assert_eq!(form.to_string(), builder.finish().to_string());
+
+    // The multipart body is signed in the same way as multipart::sign
+    let mut form = multipart::MultipartForm::with_boundary("boundary").unwrap();
+    form.text("status", "a b!");
+    assert_eq!(
+        builder.finish_with_body(Some(&form.content_type()), &form.body()).unwrap().to_string(),
+        multipart::sign(&builder, &form).authorization.to_string()
+    );
+
+    builder.header_parameter("oauth_body_hash", "x").unwrap();
+    assert_eq!(builder.finish_with_body(None, b"").err(), Some(HeaderParameterError::Duplicated));
+}
+
+#[test]
+fn signing_test() {
+    let url = Url::parse("https://Example.COM:443/r%20v?b=1").unwrap();
+    let oauth_params = [
+        ("oauth_consumer_key", "consumer"),
+        ("oauth_token", "token"),
+        ("oauth_signature_method", "HMAC-SHA1"),
+        ("oauth_timestamp", "137131200"),
+        ("oauth_nonce", "nonce")
+    ];
+
+    let base_string_uri = signing::base_string_uri("HTTPS", "example.com", Some(443), "/r%20v");
+    assert_eq!(base_string_uri, base_string_url(&url));
+
+    let base_string = signing::signature_base_string(
+        "post", &base_string_uri,
+        oauth_params.iter().map(|&(k, v)| (k.as_bytes(), v.as_bytes()))
+            .chain(vec![(&b"b"[..], &b"1"[..]), (&b"a"[..], &b"\x83e"[..])]));
+    let signature = signing::sign(SignatureMethod::HmacSha1, &base_string, "consumer_secret", Some("token_secret"));
+
+    let header = OAuthAuthorizationHeaderBuilder::new("POST", &url, "consumer", "consumer_secret", SignatureMethod::HmacSha1)
+        .token("token", "token_secret")
+        .timestamp(137131200)
+        .nonce("nonce")
+        .include_version(false)
+        .request_byte_parameters(vec![(&b"a"[..], &b"\x83e"[..])])
+        .finish();
+
+    assert_eq!(header.auth_param(), signing::auth_param(None, oauth_params.iter().cloned(), &signature));
+    assert_eq!(signing::sign(SignatureMethod::Plaintext, "", "a&b", None), "a%26b&");
+}
+
+// 1024-bit RSA key in DER-encoded PKCS #1 for the crypto backend tests
+const RSA_PRIVATE_KEY: &'static str = concat!(
+    "MIICXgIBAAKBgQC0YzrXYkBUCAlxTlKLma/3Va7AeWrRb8gXrSj4nf4cjH2GCTV44B5Y59ZL2IA1",
+    "HSbvZs/3ZSSLnpta1I9fZhiENSjm6YY12dNKZHAs/dDSnstiBYgh6TJtsSNj8OceCttgcbSuiBT9",
+    "R90AmU3te2xTbi86buWymj4HW0nbNwd8NQIDAQABAoGBAIC9lUAk4g5XEg4ovObfD+Qw5aB+x0qk",
+    "QaUMM4KEl9heODPSHLShF2eSRrIo2Q+PN7uCoAhLyjJPR0cjDxQh9HorqQ5K9qxm/ldMP8I5VuaP",
+    "B+ofMD4o49TPphHzSUo/5H+yQF4Ps7vCapvNLW1HLpr9+j9IKX/+e3/mhhWlgGThAkEA4giySNF3",
+    "+clv7h2WP2VT2MDIs+IX8sNseDHr99ZOZeZrnP+XFMHVLq3bZz21r03HA22k1Bbl74hpqagYcNaR",
+    "yQJBAMxNWsgY3gFByzXkK0ayj3NxLXZwD1TGpNvcReE9RG/YjAk711TAnnTZGAGXT8AZySwbrvn3",
+    "gM1kA2iTOntT7Q0CQQCQJiGk5MlRqFBUl9PQ3EsLqW6VoXPKYyzSvmuRI7/dpoQsnKd6Gl9dJI0O",
+    "m0E56i0oF42NoIjFKMbR5pynoX9ZAkAvev7RKC5YPnE6yhdgq9j47GGzd+GoQy5HRo8bBMEl+T66",
+    "WqT1tnpdD6Mro33OWG/Ph3OH9oSBt2+vWyApT1z1AkEAza9zrui7qNiMEhhqBwuoTwj/H+1Wv2lf",
+    "wpKQyzbSEZgKbI3AAjHmj3Ktc/2UOrOnngCfPL2Rz4thzUrqmYBVzw==",
+);
+
+const RSA_PUBLIC_KEY: &'static str = concat!(
+    "MIGJAoGBALRjOtdiQFQICXFOUouZr/dVrsB5atFvyBetKPid/hyMfYYJNXjgHljn1kvYgDUdJu9m",
+    "z/dlJIuem1rUj19mGIQ1KObphjXZ00pkcCz90NKey2IFiCHpMm2xI2Pw5x4K22BxtK6IFP1H3QCZ",
+    "Te17bFNuLzpu5bKaPgdbSds3B3w1AgMBAAE=",
+);
+
+#[test]
+fn crypto_test() {
+    fn hex(x: Vec<u8>) -> String {
+        x.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    // RFC 2202 and RFC 4231
+    assert_eq!(hex(crypto::hmac_sha1(b"Jefe", b"what do ya want for nothing?")), "effcdf6ae5eb2fa2d27416d5f184df9c259a7c79");
+    assert_eq!(
+        hex(crypto::hmac_sha256(b"Jefe", b"what do ya want for nothing?")),
+        "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+    );
+    assert_eq!(hex(crypto::sha1(b"abc")), "a9993e364706816aba3e25717850c26c9cd0d89d");
+    assert_eq!(hex(crypto::sha256(b"abc")), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+
+    assert!(crypto::constant_time_eq(b"abc", b"abc"));
+    assert!(!crypto::constant_time_eq(b"abc", b"abd"));
+    assert!(!crypto::constant_time_eq(b"abc", b"ab"));
+
+    let private_key = base64::decode(RSA_PRIVATE_KEY).unwrap();
+    let public_key = base64::decode(RSA_PUBLIC_KEY).unwrap();
+    let msg = b"GET&http%3A%2F%2Fphotos.example.net%2Fphotos&file%3Dvacation.jpg";
+
+    let signature = base64::decode(concat!(
+        "eRnTQqxAv0DTjiLaX3j3xg6ixOKvxOB692XEhmud371FtBIQuKmzy02r4L7p0sKciEzm3ioOVZmN2ZDkJuOi",
+        "/U4zMtcGRU//zoe/D1W7K690fvMe9UytqT4GWguDekSW9Eigj4n4ZZIO8Opt2LWPAfJ8ngxuOeewSclU7x/B6sk="
+    )).unwrap();
+
+    if crypto::BACKEND == "ring" {
+        assert_eq!(crypto::rsa_sha1_sign(&private_key, msg), Err(crypto::CryptoError::Unsupported));
+    } else {
+        assert_eq!(crypto::rsa_sha1_sign(&private_key, msg), Ok(signature.clone()));
+        assert_eq!(crypto::rsa_sha1_sign(b"invalid", msg), Err(crypto::CryptoError::InvalidKey));
+    }
+    assert_eq!(crypto::rsa_sha1_verify(&public_key, msg, &signature), Ok(true));
+    assert_eq!(crypto::rsa_sha1_verify(&public_key, b"GET", &signature), Ok(false));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde_test() {
+    extern crate serde_json;
+
+    assert_eq!(serde_json::to_string(&SignatureMethod::HmacSha1).unwrap(), "\"HMAC-SHA1\"");
+    assert_eq!(serde_json::from_str::<SignatureMethod>("\"PLAINTEXT\"").unwrap(), SignatureMethod::Plaintext);
+    assert!(serde_json::from_str::<SignatureMethod>("\"hmac-sha1\"").is_err());
+    assert!(serde_json::from_str::<SignatureMethod>("\"HMAC-SHA256\"").unwrap_err().to_string()
+        .contains("expected `HMAC-SHA1` or `PLAINTEXT`"));
+
+    let header: OAuthAuthorizationHeader = serde_json::from_str("\"OAuth oauth_consumer_key=\\\"foo\\\"\"").unwrap();
+    assert_eq!(serde_json::to_string(&header).unwrap(), "\"oauth_consumer_key=\\\"foo\\\"\"");
+    assert!(serde_json::from_str::<OAuthAuthorizationHeader>("\"oauth_consumer_key=foo\"").is_err());
+
+    let credentials = ClientCredentials::new("consumer", "secret");
+    let json = serde_json::to_string(&credentials).unwrap();
+    assert_eq!(json, "{\"key\":\"consumer\",\"secret\":\"secret\"}");
+    assert_eq!(serde_json::from_str::<ClientCredentials>(&json).unwrap(), credentials);
+
+    let url = Url::parse("http://example.com/").unwrap();
+    let header = OAuthAuthorizationHeaderBuilder::new("GET", &url, "consumer", "secret", SignatureMethod::HmacSha1)
+        .realm("Example")
+        .token("token", "token_secret")
+        .finish();
+    let req = SignedRequest::from_header(&header).unwrap();
+    let json = serde_json::to_string(&req).unwrap();
+    assert_eq!(json, serde_json::to_string(&header).unwrap());
+
+    let req: SignedRequest = serde_json::from_str(&json).unwrap();
+    assert_eq!(req.verify("GET", &url, Vec::<(&str, &str)>::new(), "secret", Some("token_secret")), Ok(()));
+    assert!(serde_json::from_str::<SignedRequest>("\"oauth_consumer_key=\\\"foo\\\"\"").is_err());
+}
+
+#[test]
+fn signer_registry_test() {
+    use signer::*;
+
+    struct ReverseSigner;
+
+    impl Signer for ReverseSigner {
+        fn sign(&self, _: &str, consumer_secret: &str, _: Option<&str>) -> Result<String, crypto::CryptoError> {
+            Ok(consumer_secret.chars().rev().collect())
+        }
+    }
+
+    assert_eq!("HMAC-SHA256".parse::<SignatureMethod>(), Err(ParseSignatureMethodError));
+    assert_eq!("PLAINTEXT".parse(), Ok(SignatureMethod::Plaintext));
+    assert_eq!("plaintext".parse::<SignatureMethod>(), Err(ParseSignatureMethodError));
+    assert_eq!("RSA-SHA1".parse::<SignatureMethod>(), Err(ParseSignatureMethodError));
+
+    let mut registry = SignerRegistry::new();
+    registry.register("X-REVERSE", ReverseSigner);
+
+    let url = Url::parse("http://photos.example.net/photos?file=vacation.jpg&size=original").unwrap();
+    let header = OAuthAuthorizationHeaderBuilder::new("GET", &url, "dpf43f3p2l4k3l03", "kd94hf93k423kf44", SignatureMethod::HmacSha1)
+        .registered_signature_method(&registry, "HMAC-SHA256").unwrap()
+        .token("nnch734d00sl2jdk", "pfkkdhi9sl3r4s00")
+        .timestamp(137131202)
+        .nonce("chapoH")
+        .include_version(false)
+        .finish();
+    assert!(header.auth_param().ends_with("oauth_signature=\"HtMwoX2zenlFjgGg%2FSNEoKEQmL7CzxYFEKzs7er044Y%3D\""));
+    let req = SignedRequest::from_header_with_registry(&header, &registry).unwrap();
+    assert_eq!(req.signature_method_name(), "HMAC-SHA256");
+    assert_eq!(req.verify_with(VerifyOptions::new().registry(&registry), "GET", &url, Vec::<(&[u8], &[u8])>::new(), "kd94hf93k423kf44", Some("pfkkdhi9sl3r4s00")), Ok(()));
+
+    assert_eq!(OAuthAuthorizationHeaderBuilder::new("GET", &url, "consumer", "secret", SignatureMethod::HmacSha1)
+        .registered_signature_method(&registry, "X-UNKNOWN").err(), Some(SignerError::NotRegistered));
+
+    let header = OAuthAuthorizationHeaderBuilder::new("GET", &url, "consumer", "secret", SignatureMethod::HmacSha1)
+        .registered_signature_method(&registry, "X-REVERSE").unwrap()
+        .finish_for_twitter();
+    assert!(header.auth_param().contains("oauth_signature_method=\"X-REVERSE\""));
+    assert!(header.auth_param().ends_with("oauth_signature=\"terces\""));
+
+    assert_eq!(SignedRequest::from_header(&header).err(), Some(VerifyError::SignatureMethodRejected));
+    let req = SignedRequest::from_header_with_registry(&header, &registry).unwrap();
+    assert_eq!(req.signature_method_name(), "X-REVERSE");
+    assert_eq!(req.verify_with(VerifyOptions::new().registry(&registry).for_twitter(true), "GET", &url, Vec::<(&[u8], &[u8])>::new(), "secret", None), Ok(()));
+    assert_eq!(req.verify_with(VerifyOptions::new().registry(&registry), "GET", &url, Vec::<(&[u8], &[u8])>::new(), "secret", None), Ok(()));
+    assert_eq!(req.verify("GET", &url, Vec::<(&str, &str)>::new(), "secret", None), Err(VerifyError::SignatureMethodRejected));
+
+    registry.unregister("X-REVERSE");
+    assert_eq!(SignedRequest::from_header_with_registry(&header, &registry).err(), Some(VerifyError::SignatureMethodRejected));
+
+    let mut consumer = SignerRegistry::empty();
+    consumer.register("RSA-SHA1", RsaSha1Signer::new(base64::decode(RSA_PRIVATE_KEY).unwrap()));
+    if crypto::BACKEND == "ring" {
+        assert_eq!(OAuthAuthorizationHeaderBuilder::new("GET", &url, "consumer", "", SignatureMethod::HmacSha1)
+            .registered_signature_method(&consumer, "RSA-SHA1").err(),
+            Some(SignerError::Crypto(crypto::CryptoError::Unsupported)));
+    } else {
+        let mut provider = SignerRegistry::empty();
+        provider.register("RSA-SHA1", RsaSha1Signer::verifier(base64::decode(RSA_PUBLIC_KEY).unwrap()));
+
+        let header = OAuthAuthorizationHeaderBuilder::new("GET", &url, "consumer", "", SignatureMethod::HmacSha1)
+            .registered_signature_method(&consumer, "RSA-SHA1").unwrap()
+            .finish();
+        let req = SignedRequest::from_header_with_registry(&header, &provider).unwrap();
+        let options = *VerifyOptions::new().registry(&provider);
+        assert_eq!(req.verify_with(&options, "GET", &url, Vec::<(&[u8], &[u8])>::new(), "", None), Ok(()));
+        assert_eq!(req.verify_with(&options, "POST", &url, Vec::<(&[u8], &[u8])>::new(), "", None), Err(VerifyError::SignatureInvalid));
+    }
+}
+
+#[test]
+fn send_sync_test() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<OAuthAuthorizationHeaderBuilder>();
+    assert_send_sync::<signer::SignerRegistry>();
+}
+
+#[test]
+fn secret_test() {
+    let consumer_secret = ConsumerSecret::new(String::from("consumer_secret"));
+    let token_secret = TokenSecret::from("token_secret");
+    assert_eq!(format!("{:?}", consumer_secret), "ConsumerSecret([REDACTED])");
+    assert_eq!(format!("{}", token_secret), "[REDACTED]");
+    assert_eq!(format!("{:?}", PrivateKey::new(vec![1, 2, 3])), "PrivateKey([REDACTED])");
+    assert_eq!(token_secret, TokenSecret::new(String::from("token_secret")));
+    assert!(token_secret != TokenSecret::from("token_secreT"));
+
+    let credentials = TokenCredentials::new("token", "token_secret");
+    assert!(!format!("{:?}", credentials).contains("token_secret"));
+
+    let res = OAuthTokenResponse::from_body(
+        "oauth_token=token&oauth_token_secret=hdhd0244k9j7ao03&oauth_session_handle=handle&user_id=1").unwrap();
+    assert!(!format!("{:?}", res).contains("hdhd0244k9j7ao03"));
+    assert!(!format!("{:?}", OAuthSession::from_token_response(&res, 0).unwrap()).contains("hdhd0244k9j7ao03"));
+    assert!(!format!("{:?}", twitter::AccessToken::from_body(&res.to_body()).unwrap()).contains("hdhd0244k9j7ao03"));
+
+    let owned = String::from("token_secret");
+    assert_eq!(TokenSecret::from(&owned), token_secret);
+    let mut set = std::collections::HashSet::new();
+    set.insert(credentials.clone());
+    assert!(set.contains(&TokenCredentials::new("token", owned.clone())));
+
+    let url = Url::parse("http://example.com/").unwrap();
+    let expected = OAuthAuthorizationHeaderBuilder::new("GET", &url, "consumer", "consumer_secret", SignatureMethod::HmacSha1)
+        .token("token", "token_secret")
+        .nonce("nonce")
+        .timestamp(0)
+        .finish();
+    let header = OAuthAuthorizationHeaderBuilder::new("GET", &url, "consumer", consumer_secret, SignatureMethod::HmacSha1)
+        .token("token", &credentials.secret)
+        .nonce("nonce")
+        .timestamp(0)
+        .finish();
+    assert_eq!(header.auth_param(), expected.auth_param());
+}
+
+#[cfg(feature = "tracing")]
+#[test]
+fn tracing_test() {
+    use std::fmt::Debug;
+    use std::sync::{Arc, Mutex};
+    use tracing::{Event, Id, Metadata, Subscriber};
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Record};
+
+    struct Recorder(Arc<Mutex<String>>);
+
+    impl Visit for Recorder {
+        fn record_debug(&mut self, field: &Field, value: &Debug) {
+            let mut s = self.0.lock().unwrap();
+            s.push_str(&format!("{}={:?} ", field.name(), value));
+        }
+    }
+
+    struct TestSubscriber(Arc<Mutex<String>>);
+
+    impl Subscriber for TestSubscriber {
+        fn enabled(&self, _: &Metadata) -> bool { true }
+        fn new_span(&self, span: &Attributes) -> Id {
+            span.record(&mut Recorder(self.0.clone()));
+            Id::from_u64(1)
+        }
+        fn record(&self, _: &Id, values: &Record) { values.record(&mut Recorder(self.0.clone())); }
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+        fn event(&self, event: &Event) { event.record(&mut Recorder(self.0.clone())); }
+        fn enter(&self, _: &Id) {}
+        fn exit(&self, _: &Id) {}
+    }
+
+    let log = Arc::new(Mutex::new(String::new()));
+    let url = Url::parse("https://example.com/resource?b=1&a=2").unwrap();
+
+    tracing::subscriber::with_default(TestSubscriber(log.clone()), || {
+        let header = OAuthAuthorizationHeaderBuilder::new("GET", &url, "consumer", "consumer_secret", SignatureMethod::HmacSha1)
+            .token("token", "token_secret")
+            .finish_for_twitter();
+        let req = SignedRequest::from_header(&header).unwrap();
+        assert_eq!(
+            req.verify("GET", &url, Vec::<(&str, &str)>::new(), "wrong_secret", Some("token_secret")),
+            Err(VerifyError::SignatureInvalid)
+        );
+    });
+
+    let log = log.lock().unwrap();
+    assert!(log.contains("base_string_url=https://example.com/resource "));
+    assert!(log.contains("parameters=[\"a\", \"b\", \"oauth_consumer_key\", \"oauth_nonce\", \
+        \"oauth_signature_method\", \"oauth_timestamp\", \"oauth_token\", \"oauth_version\"]"));
+    assert!(log.contains("signature_method=HMAC-SHA1 quirks=\"twitter\""));
+    assert!(log.contains("quirks=\"rfc5849\""));
+    assert!(log.contains("The signature does not match"));
+    assert!(!log.contains("secret"));
+}
+
+#[test]
+fn diagnosis_test() {
+    use diagnosis::{DiagnoseError, Variation};
+
+    let url = Url::parse("https://example.com/path?a=%7e").unwrap();
+    let mut builder = OAuthAuthorizationHeaderBuilder::new("GET", &url, "consumer", "secret", SignatureMethod::HmacSha1);
+    builder.token("token", "token_secret").nonce("nonce").timestamp(0);
+
+    let signature = |header: OAuthAuthorizationHeader| SignedRequest::from_header(&header).unwrap().signature().to_owned();
+    assert_eq!(builder.diagnose(&signature(builder.finish())), Ok(Some(vec![])));
+    assert_eq!(builder.diagnose(&signature(builder.finish_for_twitter())), Ok(Some(vec![Variation::TwitterQuery])));
+
+    builder.include_version(false);
+    let expected = signature(builder.finish_for_twitter());
+    builder.include_version(true);
+    assert_eq!(builder.diagnose(&expected), Ok(Some(vec![Variation::TwitterQuery, Variation::ToggleVersion])));
+
+    let url = Url::parse("https://example.com/path").unwrap();
+    let mut builder = OAuthAuthorizationHeaderBuilder::new("GET", &url, "consumer", "secret", SignatureMethod::HmacSha1);
+    builder.request_parameters(vec![("a", "2"), ("a", "1")]).nonce("nonce");
+    assert_eq!(builder.diagnose("XsUrTLfXvzjqHnwmpaQAxMVORLo="), Err(DiagnoseError::TimestampAbsent));
+    builder.timestamp(0);
+    assert_eq!(builder.diagnose("XsUrTLfXvzjqHnwmpaQAxMVORLo="), Ok(Some(vec![Variation::DefaultPort, Variation::UnsortedDuplicates])));
+    assert_eq!(builder.diagnose("XsUrTLfXvzjqHnwmpaQAxMVORLo"), Ok(None));
+    assert_eq!(
+        OAuthAuthorizationHeaderBuilder::new("GET", &url, "consumer", "secret", SignatureMethod::HmacSha1)
+            .timestamp(0)
+            .diagnose(""),
+        Err(DiagnoseError::NonceAbsent)
+    );
+
+    let url = Url::parse("data:text/plain,a").unwrap();
+    assert_eq!(
+        OAuthAuthorizationHeaderBuilder::new("GET", &url, "consumer", "secret", SignatureMethod::HmacSha1)
+            .timestamp(0)
+            .nonce("nonce")
+            .diagnose(""),
+        Err(DiagnoseError::InvalidUrl)
+    );
+}
+
+#[test]
+fn callback_test() {
+    let url = Url::parse("https://example.com/callback?state=1&oauth_token=token%2B1&oauth_verifier=verifier").unwrap();
+    let callback = OAuthCallback::from_url(&url).unwrap();
+    assert_eq!(callback.token(), "token+1");
+    assert_eq!(callback.verifier_for("token+1"), Ok("verifier"));
+    assert_eq!(callback.verifier_for("token 1"), Err(ParseOAuthCallbackError::TokenMismatch));
+
+    let parse = |s| OAuthCallback::from_url(&Url::parse(s).unwrap());
+    assert_eq!(parse("https://example.com/callback?oauth_token=token"),
+        Err(ParseOAuthCallbackError::ParameterAbsent("oauth_verifier")));
+    assert_eq!(parse("https://example.com/callback?oauth_verifier=verifier"),
+        Err(ParseOAuthCallbackError::ParameterAbsent("oauth_token")));
+    assert_eq!(parse("https://example.com/callback?denied=token"), Err(ParseOAuthCallbackError::Denied));
+    assert_eq!(parse("https://example.com/callback?oauth_token=token&oauth_problem=user_refused"),
+        Err(ParseOAuthCallbackError::Denied));
+}
+
+#[test]
+fn flow_test() {
+    use flow::*;
+
+    let url = Url::parse("https://example.com/oauth/request_token").unwrap();
+    let header = RequestToken::request(&url, "consumer", "consumer_secret", SignatureMethod::Plaintext, "oob").finish();
+    assert!(header.auth_param().contains("oauth_callback=\"oob\""));
+    assert!(!header.auth_param().contains("oauth_token="));
+
+    let res = OAuthTokenResponse::from_body("oauth_token=request&oauth_token_secret=request_secret").unwrap();
+    assert_eq!(RequestToken::from_response(&res), Err(FlowError::CallbackNotConfirmed));
+    let res = OAuthTokenResponse::from_body(
+        "oauth_token=request&oauth_token_secret=request_secret&oauth_callback_confirmed=false").unwrap();
+    assert_eq!(RequestToken::from_response(&res), Err(FlowError::CallbackNotConfirmed));
+
+    let res = OAuthTokenResponse::from_body(
+        "oauth_token=request&oauth_token_secret=request_secret&oauth_callback_confirmed=true").unwrap();
+    let request_token = RequestToken::from_response(&res).unwrap();
+    assert_eq!(RequestToken::from_confirmed_parts("request", String::from("request_secret")), request_token);
+    let callback = OAuthCallback::from_url(
+        &Url::parse("https://client.example.com/callback?oauth_token=other&oauth_verifier=verifier").unwrap()).unwrap();
+    assert_eq!(request_token.clone().authorize(&callback).err(),
+        Some(FlowError::CallbackError(ParseOAuthCallbackError::TokenMismatch)));
+
+    let authorized = request_token.authorize_with_verifier("verifier");
+    let url = Url::parse("https://example.com/oauth/access_token").unwrap();
+    let header = authorized.access_token_request(&url, "consumer", "consumer_secret", SignatureMethod::Plaintext).finish();
+    let req = SignedRequest::from_header(&header).unwrap();
+    assert_eq!(req.token(), Some("request"));
+    assert_eq!(req.parameter("oauth_verifier"), Some("verifier"));
+    assert_eq!(req.signature(), "consumer_secret&request_secret");
+
+    let res = OAuthTokenResponse::from_body("oauth_token=access&oauth_token_secret=access_secret").unwrap();
+    let access_token = authorized.into_access_token(&res);
+    let url = Url::parse("https://example.com/resource").unwrap();
+    let header = access_token.request("GET", &url, "consumer", "consumer_secret", SignatureMethod::HmacSha1).finish();
+    let req = SignedRequest::from_header(&header).unwrap();
+    assert_eq!(req.token(), Some("access"));
+    assert_eq!(req.parameter("oauth_verifier"), None);
+    assert_eq!(req.verify("GET", &url, Vec::<(&str, &str)>::new(), "consumer_secret", Some("access_secret")), Ok(()));
+    assert_eq!(access_token.into_credentials(), TokenCredentials::new("access", "access_secret"));
+}
+
+#[test]
+fn store_test() {
+    use store::*;
+
+    fn check<S: PendingTokenStore>(store: &S) where S::Error: ::std::fmt::Debug {
+        store.insert("token", &TokenSecret::from("secret ="), 1000).unwrap();
+        store.insert("expired", &TokenSecret::from("secret"), 900).unwrap();
+        store.insert("removed", &TokenSecret::from("secret"), 900).unwrap();
+
+        assert_eq!(store.take("token", 1059).unwrap(), Some(TokenSecret::from("secret =")));
+        assert_eq!(store.take("token", 1059).unwrap(), None);
+        assert_eq!(store.take("unknown", 1059).unwrap(), None);
+        assert_eq!(store.take("expired", 960).unwrap(), None);
+
+        store.remove_expired(960).unwrap();
+        assert_eq!(store.take("removed", 0).unwrap(), None);
+    }
+
+    check(&MemoryTokenStore::new(60));
+
+    let path = ::std::env::temp_dir().join(format!("oauthcli-store-test-{}", ::std::process::id()));
+    check(&FileTokenStore::new(path.clone(), 60));
+
+    let store = FileTokenStore::new(path.clone(), 60);
+    store.insert("token", &TokenSecret::from("secret"), 1000).unwrap();
+    assert_eq!(FileTokenStore::new(path.clone(), 60).take("token", 1000).unwrap(), Some(TokenSecret::from("secret")));
+
+    // Separate instances don't lose each other's insertions
+    let threads: Vec<_> = (0..8).map(|i| {
+        let path = path.clone();
+        ::std::thread::spawn(move || {
+            FileTokenStore::new(path, 60).insert(&format!("token{}", i), &TokenSecret::from("secret"), 1000).unwrap();
+        })
+    }).collect();
+    for t in threads { t.join().unwrap(); }
+    for i in 0..8 {
+        assert!(store.take(&format!("token{}", i), 1000).unwrap().is_some());
+    }
+
+    ::std::fs::remove_file(&path).unwrap();
+    let mut lock_path = path.into_os_string();
+    lock_path.push(".lock");
+    ::std::fs::remove_file(&lock_path).unwrap();
+}
+
+#[test]
+fn callback_prefix_test() {
+    use provider::is_valid_callback;
+
+    let prefix = Some("https://client.example.com/oauth");
+    assert!(is_valid_callback("oob", prefix));
+    assert!(is_valid_callback("https://client.example.com/oauth", prefix));
+    assert!(is_valid_callback("https://client.example.com:443/oauth/callback?a=b", prefix));
+    assert!(!is_valid_callback("https://client.example.com/oauthevil", prefix));
+    assert!(!is_valid_callback("https://client.example.com.evil.example/oauth", prefix));
+    assert!(!is_valid_callback("https://client.example.com@evil.example/oauth", prefix));
+    assert!(!is_valid_callback("https://client.example.com:8443/oauth", prefix));
+    assert!(!is_valid_callback("http://client.example.com/oauth", prefix));
+    assert!(is_valid_callback("https://client.example.com/a", Some("https://client.example.com")));
+    assert!(is_valid_callback("http://evil.example/", None));
+    assert!(!is_valid_callback("javascript:alert(1)", None));
+}
+
+#[test]
+fn provider_test() {
+    use flow::*;
+    use provider::*;
+
+    let store = MemoryProviderStore::new();
+    store.insert_consumer(ConsumerRecord {
+        credentials: ClientCredentials::new("consumer", "consumer_secret"),
+        callback_prefix: Some("https://client.example.com/".to_owned())
+    });
+    let mut provider = Provider::new(store);
+    let now = 1500000000;
+
+    let url = Url::parse("https://example.com/oauth/request_token").unwrap();
+    let header = RequestToken::request(&url, "consumer", "consumer_secret", SignatureMethod::HmacSha1,
+        "https://evil.example.com/").timestamp(now).finish();
+    let err = provider.request_token("POST", &url, &header, &[], now).unwrap_err();
+    assert_eq!((err.status_code(), &err), (400, &ProviderError::Problem(OAuthProblemReport {
+        parameters_rejected: vec!["oauth_callback".to_owned()],
+        ..OAuthProblemReport::new(OAuthProblem::ParameterRejected)
+    })));
+
+    let header = RequestToken::request(&url, "consumer", "consumer_secret", SignatureMethod::HmacSha1,
+        "https://client.example.com/callback").timestamp(now).finish();
+    let err = provider.request_token("POST", &url, &header, &[], now + 600).unwrap_err();
+    assert_eq!(err, ProviderError::Problem(OAuthProblemReport {
+        acceptable_timestamps: Some((now + 300, now + 900)),
+        ..OAuthProblemReport::new(OAuthProblem::TimestampRefused)
+    }));
+    let res = provider.request_token("POST", &url, &header, &[], now).unwrap();
+    assert_eq!(OAuthTokenResponse::from_body(&res.to_body()), Ok(res.clone()));
+    let err = provider.request_token("POST", &url, &header, &[], now).unwrap_err();
+    assert_eq!(err, ProviderError::Problem(OAuthProblemReport::new(OAuthProblem::NonceUsed)));
+
+    let request_token = RequestToken::from_response(&res).unwrap();
+    let authorization = provider.authorize(request_token.token(), "user", now).unwrap();
+    assert_eq!(provider.authorize(request_token.token(), "user", now).unwrap_err(),
+        ProviderError::Problem(OAuthProblemReport::new(OAuthProblem::TokenUsed)));
+    // The verifier is set only once
+    assert_eq!(provider.store().authorize_request_token(request_token.token(), "other", "verifier", now), Ok(false));
+    let callback = OAuthCallback::from_url(&authorization.redirect_url().unwrap()).unwrap();
+
+    let url = Url::parse("https://example.com/oauth/access_token").unwrap();
+    let wrong = request_token.clone().authorize_with_verifier("wrong");
+    let header = wrong.access_token_request(&url, "consumer", "consumer_secret", SignatureMethod::HmacSha1).timestamp(now).finish();
+    assert_eq!(provider.access_token("POST", &url, &header, &[], now).unwrap_err().status_code(), 400);
+
+    let authorized = request_token.authorize(&callback).unwrap();
+    let header = authorized.access_token_request(&url, "consumer", "consumer_secret", SignatureMethod::HmacSha1).timestamp(now).finish();
+    let res = provider.access_token("POST", &url, &header, &[], now).unwrap();
+    let header = authorized.access_token_request(&url, "consumer", "consumer_secret", SignatureMethod::HmacSha1).timestamp(now).finish();
+    assert_eq!(provider.access_token("POST", &url, &header, &[], now).unwrap_err(),
+        ProviderError::Problem(OAuthProblemReport::new(OAuthProblem::TokenRejected)));
+
+    let access_token = authorized.into_access_token(&res);
+    let url = Url::parse("https://example.com/resource").unwrap();
+    let body = decode_form_body(b"status=hello%20world");
+    let header = access_token.request("POST", &url, "consumer", "consumer_secret", SignatureMethod::HmacSha1)
+        .request_parameters(vec![("status", "hello world")]).timestamp(now).finish();
+    let record = provider.authenticate("POST", &url, &header, &body, now).unwrap();
+    assert_eq!((&record.user[..], &record.credentials.token[..]), ("user", access_token.token()));
+    assert_eq!(provider.authenticate("POST", &url, &header, &[], now).unwrap_err(),
+        ProviderError::Problem(OAuthProblemReport::new(OAuthProblem::SignatureInvalid)));
+
+    // PLAINTEXT is accepted only over HTTPS
+    let header = access_token.request("GET", &url, "consumer", "consumer_secret", SignatureMethod::Plaintext)
+        .timestamp(now).finish();
+    assert!(provider.authenticate("GET", &url, &header, &[], now).is_ok());
+    let http_url = Url::parse("http://example.com/resource").unwrap();
+    let header = access_token.request("GET", &http_url, "consumer", "consumer_secret", SignatureMethod::Plaintext)
+        .timestamp(now).finish();
+    assert_eq!(provider.authenticate("GET", &http_url, &header, &[], now).unwrap_err(),
+        ProviderError::Problem(OAuthProblemReport::new(OAuthProblem::SignatureMethodRejected)));
+
+    let mut signers = signer::SignerRegistry::empty();
+    signers.register("HMAC-SHA256", signer::HmacSha256Signer);
+    provider.signers(signers);
+    let header = access_token.request("GET", &url, "consumer", "consumer_secret", SignatureMethod::HmacSha1)
+        .timestamp(now).finish();
+    assert_eq!(provider.authenticate("GET", &url, &header, &[], now).unwrap_err(),
+        ProviderError::Problem(OAuthProblemReport::new(OAuthProblem::SignatureMethodRejected)));
+    let registry = signer::SignerRegistry::new();
+    let header = access_token.request("GET", &url, "consumer", "consumer_secret", SignatureMethod::HmacSha1)
+        .registered_signature_method(&registry, "HMAC-SHA256").unwrap()
+        .timestamp(now).finish();
+    assert!(provider.authenticate("GET", &url, &header, &[], now).is_ok());
+
+    provider.remove_expired(now + 600).unwrap();
+    assert!(provider.store().revoke_access_token(access_token.token()).unwrap());
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn sqlite_test() {
+    use flow::*;
+    use provider::*;
+    use sqlite::*;
+    use store::*;
+
+    let path = ::std::env::temp_dir().join(format!("oauthcli-sqlite-test-{}", ::std::process::id()));
+    let now = 1500000000;
+    {
+        let mut store = SqliteStore::open(&path).unwrap();
+        store.pending_token_ttl(60);
+        store.insert_consumer(&ConsumerRecord {
+            credentials: ClientCredentials::new("consumer", "consumer_secret"),
+            callback_prefix: None
+        }).unwrap();
+        store.insert("pending", &TokenSecret::from("pending_secret"), now).unwrap();
+        store.insert("expired", &TokenSecret::from("secret"), now - 60).unwrap();
+        assert!(store.use_nonce("consumer", None, now, "nonce").unwrap());
+        assert!(!store.use_nonce("consumer", None, now, "nonce").unwrap());
+        assert!(store.use_nonce("consumer", Some("token"), now, "nonce").unwrap());
+    }
+
+    // The data survive reopening, and the migrations are not applied twice
+    let provider = Provider::new(SqliteStore::open(&path).unwrap());
+    let store = provider.store();
+    assert_eq!(store.take("pending", now).unwrap(), Some(TokenSecret::from("pending_secret")));
+    assert_eq!(store.take("pending", now).unwrap(), None);
+    assert_eq!(store.take("expired", now).unwrap(), None);
+    assert!(!store.use_nonce("consumer", None, now, "nonce").unwrap());
+
+    // The times beyond i64 are saturated
+    let mut long_lived = SqliteStore::open_in_memory().unwrap();
+    long_lived.pending_token_ttl(u64::MAX);
+    long_lived.insert("forever", &TokenSecret::from("secret"), now).unwrap();
+    long_lived.remove_expired(now).unwrap();
+    assert_eq!(long_lived.take("forever", i64::MAX as u64 - 1).unwrap(), Some(TokenSecret::from("secret")));
+
+    let url = Url::parse("https://example.com/oauth/request_token").unwrap();
+    let header = RequestToken::request(&url, "consumer", "consumer_secret", SignatureMethod::HmacSha1, "oob")
+        .timestamp(now).finish();
+    let request_token = RequestToken::from_response(&provider.request_token("POST", &url, &header, &[], now).unwrap()).unwrap();
+    let authorization = provider.authorize(request_token.token(), "user", now).unwrap();
+    assert_eq!(authorization.redirect_url(), None);
+    let record = store.request_token(request_token.token(), now).unwrap().unwrap();
+    assert_eq!((record.user, record.verifier), (Some("user".to_owned()), Some(authorization.verifier.clone())));
+    assert!(!store.authorize_request_token(request_token.token(), "other", "verifier", now).unwrap());
+
+    let authorized = request_token.authorize_with_verifier(authorization.verifier);
+    let url = Url::parse("https://example.com/oauth/access_token").unwrap();
+    let header = authorized.access_token_request(&url, "consumer", "consumer_secret", SignatureMethod::HmacSha1)
+        .timestamp(now).finish();
+    let res = provider.access_token("POST", &url, &header, &[], now).unwrap();
+    assert_eq!(store.request_token(authorized.token(), now).unwrap(), None);
+
+    let record = store.access_token(&res.token).unwrap().unwrap();
+    assert_eq!((&record.consumer_key[..], &record.user[..]), ("consumer", "user"));
+    assert_eq!(record.credentials, TokenCredentials::new(res.token.clone(), res.token_secret.expose()));
+
+    provider.remove_expired(now + 600).unwrap();
+    store.remove_expired(now + 600).unwrap();
+    assert!(store.use_nonce("consumer", None, now, "nonce").unwrap());
+    assert!(store.revoke_access_token(&res.token).unwrap());
+    assert!(!store.remove_consumer("unknown").unwrap());
+
+    // The schema of a newer version is not touched
+    ::rusqlite::Connection::open(&path).unwrap()
+        .execute("INSERT INTO oauthcli_migrations (version) VALUES (99)", []).unwrap();
+    assert!(SqliteStore::open(&path).is_err());
+    ::std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,129 @@
+use std::error::Error;
+use std::fmt;
+use url::form_urlencoded;
+use super::{format_form_parameters, TokenSecret};
+
+#[derive(Copy, Debug, PartialEq, Eq, Clone, Hash)]
+pub enum ParseOAuthTokenResponseError {
+    /// The required parameter is not found.
+    ParameterAbsent(&'static str),
+    /// The value of the parameter is malformed.
+    InvalidParameter(&'static str)
+}
+
+impl Error for ParseOAuthTokenResponseError {
+    fn description(&self) -> &str {
+        match *self {
+            ParseOAuthTokenResponseError::ParameterAbsent(_) => "The required parameter is not found",
+            ParseOAuthTokenResponseError::InvalidParameter(_) => "The value of the parameter is malformed"
+        }
+    }
+}
+
+impl fmt::Display for ParseOAuthTokenResponseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseOAuthTokenResponseError::ParameterAbsent(x) => write!(f, "`{}` is not found", x),
+            ParseOAuthTokenResponseError::InvalidParameter(x) => write!(f, "The value of `{}` is malformed", x)
+        }
+    }
+}
+
+/// Response of Temporary Credential Request or Token Request.
+///
+/// # Example
+/// ```
+/// # use oauthcli::OAuthTokenResponse;
+/// let res = OAuthTokenResponse::from_body(
+///     "oauth_token=hh5s93j4hdidpola&oauth_token_secret=hdhd0244k9j7ao03&oauth_callback_confirmed=true"
+/// ).unwrap();
+/// assert_eq!(res.token, "hh5s93j4hdidpola");
+/// assert_eq!(res.token_secret.expose(), "hdhd0244k9j7ao03");
+/// assert_eq!(res.callback_confirmed, Some(true));
+/// ```
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct OAuthTokenResponse {
+    /// `oauth_token`
+    pub token: String,
+    /// `oauth_token_secret`
+    pub token_secret: TokenSecret<'static>,
+    /// `oauth_callback_confirmed`
+    pub callback_confirmed: Option<bool>,
+    /// `oauth_session_handle` of OAuth Session 1.0
+    pub session_handle: Option<String>,
+    /// `oauth_expires_in` of OAuth Session 1.0
+    pub expires_in: Option<u64>,
+    /// `oauth_authorization_expires_in` of OAuth Session 1.0
+    pub authorization_expires_in: Option<u64>,
+    /// Parameters which are not listed above, such as `user_id` of Twitter.
+    pub other_parameters: Vec<(String, String)>
+}
+
+impl OAuthTokenResponse {
+    /// Parses the response body encoded as `application/x-www-form-urlencoded`.
+    pub fn from_body(s: &str) -> Result<OAuthTokenResponse, ParseOAuthTokenResponseError> {
+        fn parse_u64(s: &str, name: &'static str) -> Result<u64, ParseOAuthTokenResponseError> {
+            s.parse().map_err(|_| ParseOAuthTokenResponseError::InvalidParameter(name))
+        }
+
+        let mut token = None;
+        let mut token_secret = None;
+        let mut callback_confirmed = None;
+        let mut session_handle = None;
+        let mut expires_in = None;
+        let mut authorization_expires_in = None;
+        let mut other_parameters = Vec::new();
+
+        for (k, v) in form_urlencoded::parse(s.trim().as_bytes()) {
+            match &k[..] {
+                "oauth_token" => token = Some(v.into_owned()),
+                "oauth_token_secret" => token_secret = Some(TokenSecret::new(v.into_owned())),
+                "oauth_callback_confirmed" => callback_confirmed = match &v[..] {
+                    "true" => Some(true),
+                    "false" => Some(false),
+                    _ => return Err(ParseOAuthTokenResponseError::InvalidParameter("oauth_callback_confirmed"))
+                },
+                "oauth_session_handle" => session_handle = Some(v.into_owned()),
+                "oauth_expires_in" =>
+                    expires_in = Some(try!(parse_u64(&v, "oauth_expires_in"))),
+                "oauth_authorization_expires_in" =>
+                    authorization_expires_in = Some(try!(parse_u64(&v, "oauth_authorization_expires_in"))),
+                _ => other_parameters.push((k.into_owned(), v.into_owned()))
+            }
+        }
+
+        Ok(OAuthTokenResponse {
+            token: try!(token.ok_or(ParseOAuthTokenResponseError::ParameterAbsent("oauth_token"))),
+            token_secret: try!(token_secret.ok_or(ParseOAuthTokenResponseError::ParameterAbsent("oauth_token_secret"))),
+            callback_confirmed: callback_confirmed,
+            session_handle: session_handle,
+            expires_in: expires_in,
+            authorization_expires_in: authorization_expires_in,
+            other_parameters: other_parameters
+        })
+    }
+
+    /// Returns the value of the parameter in `other_parameters`.
+    pub fn parameter(&self, name: &str) -> Option<&str> {
+        self.other_parameters.iter()
+            .find(|&&(ref k, _)| k == name)
+            .map(|&(_, ref v)| &v[..])
+    }
+
+    /// Generates the response body encoded as `application/x-www-form-urlencoded`.
+    pub fn to_body(&self) -> String {
+        let callback_confirmed = self.callback_confirmed.map(|x| if x { "true" } else { "false" });
+        let expires_in = self.expires_in.map(|x| x.to_string());
+        let authorization_expires_in = self.authorization_expires_in.map(|x| x.to_string());
+
+        let credentials = [("oauth_token", &self.token[..]), ("oauth_token_secret", self.token_secret.expose())];
+        let params = credentials.iter().cloned()
+            .chain(callback_confirmed.map(|x| ("oauth_callback_confirmed", x)))
+            .chain(self.session_handle.as_ref().map(|x| ("oauth_session_handle", &x[..])))
+            .chain(expires_in.as_ref().map(|x| ("oauth_expires_in", &x[..])))
+            .chain(authorization_expires_in.as_ref().map(|x| ("oauth_authorization_expires_in", &x[..])))
+            .chain(self.other_parameters.iter().map(|&(ref k, ref v)| (&k[..], &v[..])));
+        format_form_parameters(params)
+    }
+}
@@ -0,0 +1,115 @@
+//! Helpers for Twitter's extensions to OAuth 1.0.
+
+use std::borrow::Cow;
+use super::{OAuthAuthorizationHeader, OAuthAuthorizationHeaderBuilder, OAuthTokenResponse, TokenSecret};
+use super::{ParseOAuthAuthorizationHeaderError, ParseOAuthTokenResponseError};
+use super::format_form_parameters;
+
+pub const REQUEST_TOKEN_URL: &'static str = "https://api.twitter.com/oauth/request_token";
+pub const ACCESS_TOKEN_URL: &'static str = "https://api.twitter.com/oauth/access_token";
+
+/// `Content-Type` of the request body generated by this module.
+pub const FORM_CONTENT_TYPE: &'static str = "application/x-www-form-urlencoded";
+
+/// POST request which has `Authorization` header and a form-encoded body.
+#[derive(Debug, Clone)]
+pub struct SignedFormRequest {
+    /// The value of `Authorization` header.
+    pub authorization: OAuthAuthorizationHeader,
+    /// The request body encoded with `OAUTH_ENCODE_SET`.
+    /// Send it with `FORM_CONTENT_TYPE`.
+    pub body: String
+}
+
+fn sign_form_request<'a>(builder: &OAuthAuthorizationHeaderBuilder<'a>, params: &[(&'static str, Cow<'a, str>)]) -> SignedFormRequest {
+    // Sign a copy so that the caller's builder can be reused
+    let mut builder = builder.clone();
+    builder.request_parameters(params.iter().cloned());
+
+    SignedFormRequest {
+        authorization: builder.finish_for_twitter(),
+        body: format_form_parameters(params.iter().map(|&(k, ref v)| (k, &v[..])))
+    }
+}
+
+/// Generates a [xAuth](https://developer.twitter.com/en/docs/basics/authentication/overview/xauth) request.
+///
+/// `builder` should be created for POST `ACCESS_TOKEN_URL` without any token.
+///
+/// # Example
+/// ```
+/// # extern crate oauthcli;
+/// # extern crate url;
+/// # use oauthcli::*;
+/// # fn main() {
+/// let url = url::Url::parse(twitter::ACCESS_TOKEN_URL).unwrap();
+/// let req = twitter::xauth(
+///     &OAuthAuthorizationHeaderBuilder::new("POST", &url, "consumer", "secret", SignatureMethod::HmacSha1),
+///     "username", "p@ssword");
+/// assert_eq!(req.body, "x_auth_mode=client_auth&x_auth_password=p%40ssword&x_auth_username=username");
+/// # }
+/// ```
+pub fn xauth<'a>(builder: &OAuthAuthorizationHeaderBuilder<'a>, username: &'a str, password: &'a str) -> SignedFormRequest {
+    sign_form_request(builder, &[
+        ("x_auth_mode", "client_auth".into()),
+        ("x_auth_password", password.into()),
+        ("x_auth_username", username.into())
+    ])
+}
+
+/// Generates the first request of [Reverse Auth](https://developer.twitter.com/en/docs/basics/authentication/overview/reverse-auth),
+/// which is sent from your server.
+///
+/// `builder` should be created for POST `REQUEST_TOKEN_URL` without any token.
+/// Pass the response body to `parse_reverse_auth_parameters`.
+pub fn reverse_auth_request(builder: &OAuthAuthorizationHeaderBuilder) -> SignedFormRequest {
+    sign_form_request(builder, &[("x_auth_mode", "reverse_auth".into())])
+}
+
+/// Parses the response of `reverse_auth_request`.
+///
+/// The device needs the result formatted with `to_string()` as `x_reverse_auth_parameters`.
+pub fn parse_reverse_auth_parameters(body: &str) -> Result<OAuthAuthorizationHeader, ParseOAuthAuthorizationHeaderError> {
+    body.parse()
+}
+
+/// Generates the second request of Reverse Auth, which is sent from the device.
+///
+/// `builder` should be created for POST `ACCESS_TOKEN_URL` with the credentials on the device,
+/// `target_consumer_key` is the consumer key of your application,
+/// and `parameters` is the result of `parse_reverse_auth_parameters`.
+/// Parse the response with `AccessToken::from_body`.
+pub fn reverse_auth_access_token<'a>(builder: &OAuthAuthorizationHeaderBuilder<'a>, target_consumer_key: &'a str, parameters: &OAuthAuthorizationHeader) -> SignedFormRequest {
+    sign_form_request(builder, &[
+        ("x_reverse_auth_parameters", parameters.to_string().into()),
+        ("x_reverse_auth_target", target_consumer_key.into())
+    ])
+}
+
+/// Access token issued by Twitter.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct AccessToken {
+    /// `oauth_token`
+    pub token: String,
+    /// `oauth_token_secret`
+    pub token_secret: TokenSecret<'static>,
+    /// `user_id`
+    pub user_id: Option<String>,
+    /// `screen_name`
+    pub screen_name: Option<String>
+}
+
+impl AccessToken {
+    /// Parses the response body of `ACCESS_TOKEN_URL`.
+    pub fn from_body(s: &str) -> Result<AccessToken, ParseOAuthTokenResponseError> {
+        let res = try!(OAuthTokenResponse::from_body(s));
+
+        Ok(AccessToken {
+            user_id: res.parameter("user_id").map(ToOwned::to_owned),
+            screen_name: res.parameter("screen_name").map(ToOwned::to_owned),
+            token: res.token,
+            token_secret: res.token_secret
+        })
+    }
+}
@@ -0,0 +1,381 @@
+//! Verification of signed requests for Service Providers.
+
+use std::borrow::Cow;
+use std::error::Error;
+use std::fmt;
+use std::iter;
+use url::Url;
+use super::{OAuthAuthorizationHeader, OAuthProblem, ParseOAuthAuthorizationHeaderError, SignatureMethod};
+use super::{format_auth_params, is_realm, parse_auth_params, signature_base_string, str_to_bytes};
+use super::signer::{Signer, SignerRegistry};
+#[cfg(feature = "tracing")] use super::quirks_profile;
+
+#[derive(Copy, Debug, PartialEq, Eq, Clone, Hash)]
+pub enum VerifyError {
+    /// The `Authorization` header is not valid.
+    HeaderError(ParseOAuthAuthorizationHeaderError),
+    /// The required parameter is not found.
+    ParameterAbsent(&'static str),
+    /// The protocol parameter appears more than once.
+    ParameterDuplicated,
+    /// `oauth_timestamp` is not a number.
+    InvalidTimestamp,
+    /// `oauth_version` is not `"1.0"`.
+    VersionRejected,
+    /// `oauth_signature_method` is not supported.
+    SignatureMethodRejected,
+    /// `oauth_signature` does not match.
+    SignatureInvalid
+}
+
+impl VerifyError {
+    /// Converts to the problem to be reported to the consumer.
+    pub fn to_problem(&self) -> OAuthProblem {
+        match *self {
+            VerifyError::HeaderError(_) | VerifyError::ParameterDuplicated | VerifyError::InvalidTimestamp =>
+                OAuthProblem::ParameterRejected,
+            VerifyError::ParameterAbsent(_) => OAuthProblem::ParameterAbsent,
+            VerifyError::VersionRejected => OAuthProblem::VersionRejected,
+            VerifyError::SignatureMethodRejected => OAuthProblem::SignatureMethodRejected,
+            VerifyError::SignatureInvalid => OAuthProblem::SignatureInvalid
+        }
+    }
+}
+
+impl Error for VerifyError {
+    fn description(&self) -> &str {
+        match *self {
+            VerifyError::HeaderError(ref e) => e.description(),
+            VerifyError::ParameterAbsent(_) => "The required parameter is not found",
+            VerifyError::ParameterDuplicated => "The protocol parameter appears more than once",
+            VerifyError::InvalidTimestamp => "`oauth_timestamp` is not a number",
+            VerifyError::VersionRejected => "`oauth_version` is not \"1.0\"",
+            VerifyError::SignatureMethodRejected => "`oauth_signature_method` is not supported",
+            VerifyError::SignatureInvalid => "`oauth_signature` does not match"
+        }
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            VerifyError::HeaderError(ref e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            VerifyError::ParameterAbsent(x) => write!(f, "`{}` is not found", x),
+            _ => f.write_str(self.description())
+        }
+    }
+}
+
+impl From<ParseOAuthAuthorizationHeaderError> for VerifyError {
+    fn from(e: ParseOAuthAuthorizationHeaderError) -> VerifyError {
+        VerifyError::HeaderError(e)
+    }
+}
+
+/// Options of `SignedRequest::verify_with`.
+#[derive(Clone, Copy)]
+pub struct VerifyOptions<'r> {
+    registry: Option<&'r SignerRegistry>,
+    for_twitter: bool
+}
+
+impl<'r> VerifyOptions<'r> {
+    /// Creates the options to verify the signature of the built-in signature methods.
+    pub fn new() -> Self {
+        VerifyOptions {
+            registry: None,
+            for_twitter: false
+        }
+    }
+
+    /// Uses the signer in `registry` instead of the built-in signature methods.
+    pub fn registry(&mut self, registry: &'r SignerRegistry) -> &mut Self {
+        self.registry = Some(registry);
+        self
+    }
+
+    /// Calculates the signature in the same way as `finish_for_twitter` if `for_twitter` is `true`.
+    pub fn for_twitter(&mut self, for_twitter: bool) -> &mut Self {
+        self.for_twitter = for_twitter;
+        self
+    }
+}
+
+/// Protocol parameters received in `Authorization` header.
+///
+/// Look up the secrets with `consumer_key()` and `token()`, and then call `verify()` or `verify_with()`.
+/// Checking `timestamp()` and `nonce()` is the responsibility of the Service Provider.
+///
+/// # Example
+/// ```
+/// # extern crate oauthcli;
+/// # extern crate url;
+/// # use oauthcli::*;
+/// # fn main() {
+/// let url = url::Url::parse("http://example.com/feeds/default").unwrap();
+/// let header =
+///     OAuthAuthorizationHeaderBuilder::new(
+///         "GET", &url, "consumer", "secret", SignatureMethod::HmacSha1)
+///     .requestor_id("john.doe@example.com")
+///     .finish();
+///
+/// let req = SignedRequest::from_header(&header).unwrap();
+/// assert_eq!(req.consumer_key(), "consumer");
+/// assert_eq!(req.requestor_id(), Some("john.doe@example.com"));
+/// assert!(req.verify("GET", &url, Vec::<(&str, &str)>::new(), "secret", None).is_ok());
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct SignedRequest {
+    realm: Option<String>,
+    signature_method: String,
+    signature: String,
+    timestamp: Option<u64>,
+    /// The protocol parameters except `realm` and `oauth_signature`.
+    parameters: Vec<(String, String)>
+}
+
+impl SignedRequest {
+    /// Parses the header signed with one of the built-in signature methods.
+    pub fn from_header(header: &OAuthAuthorizationHeader) -> Result<SignedRequest, VerifyError> {
+        SignedRequest::from_header_impl(header, |x| x.parse::<SignatureMethod>().is_ok())
+    }
+
+    /// Parses the header signed with one of the signature methods in `registry`.
+    pub fn from_header_with_registry(header: &OAuthAuthorizationHeader, registry: &SignerRegistry) -> Result<SignedRequest, VerifyError> {
+        SignedRequest::from_header_impl(header, |x| registry.get(x).is_some())
+    }
+
+    fn from_header_impl<F>(header: &OAuthAuthorizationHeader, is_supported: F) -> Result<SignedRequest, VerifyError>
+        where F: Fn(&str) -> bool
+    {
+        let mut realm = None;
+        let mut signature = None;
+        let mut parameters: Vec<(String, String)> = Vec::new();
+
+        for (k, v) in try!(parse_auth_params(header.auth_param(), is_realm)) {
+            if k == "realm" {
+                realm = Some(v);
+            } else if k == "oauth_signature" {
+                if signature.is_some() { return Err(VerifyError::ParameterDuplicated); }
+                signature = Some(v);
+            } else {
+                if parameters.iter().any(|&(ref x, _)| x == &k) {
+                    return Err(VerifyError::ParameterDuplicated);
+                }
+                parameters.push((k, v));
+            }
+        }
+
+        let signature_method = {
+            let find = |name| parameters.iter().find(|&&(ref k, _)| k == name).map(|&(_, ref v)| &v[..]);
+
+            if find("oauth_consumer_key").is_none() {
+                return Err(VerifyError::ParameterAbsent("oauth_consumer_key"));
+            }
+
+            match find("oauth_version") {
+                Some("1.0") | None => (),
+                Some(_) => return Err(VerifyError::VersionRejected)
+            }
+
+            match find("oauth_signature_method") {
+                Some(x) if is_supported(x) => x.to_owned(),
+                Some(_x) => {
+                    #[cfg(feature = "tracing")]
+                    debug!(signature_method = _x, "Rejected the unsupported signature method");
+                    return Err(VerifyError::SignatureMethodRejected);
+                },
+                None => return Err(VerifyError::ParameterAbsent("oauth_signature_method"))
+            }
+        };
+
+        let mut result = SignedRequest {
+            realm: realm,
+            signature_method: signature_method,
+            signature: try!(signature.ok_or(VerifyError::ParameterAbsent("oauth_signature"))),
+            timestamp: None,
+            parameters: parameters
+        };
+
+        // PLAINTEXT may omit oauth_timestamp and oauth_nonce
+        result.timestamp = match result.parameter("oauth_timestamp") {
+            Some(x) => Some(try!(x.parse().map_err(|_| VerifyError::InvalidTimestamp))),
+            None => None
+        };
+        if result.signature_method != "PLAINTEXT" {
+            if result.timestamp.is_none() { return Err(VerifyError::ParameterAbsent("oauth_timestamp")); }
+            if result.nonce().is_none() { return Err(VerifyError::ParameterAbsent("oauth_nonce")); }
+        }
+
+        Ok(result)
+    }
+
+    /// Formats the parameters as `Authorization` header again.
+    pub fn to_header(&self) -> OAuthAuthorizationHeader {
+        let params = self.realm.as_ref()
+            .map(|x| ("realm", &x[..]))
+            .into_iter()
+            .chain(self.parameters.iter().map(|&(ref k, ref v)| (&k[..], &v[..])))
+            .chain(iter::once(("oauth_signature", &self.signature[..])));
+
+        OAuthAuthorizationHeader { s: format_auth_params(params) }
+    }
+
+    pub fn realm(&self) -> Option<&str> {
+        self.realm.as_ref().map(|x| &x[..])
+    }
+
+    /// Returns the value of the protocol parameter such as `oauth_callback` or `xoauth_*`.
+    pub fn parameter(&self, name: &str) -> Option<&str> {
+        self.parameters.iter()
+            .find(|&&(ref k, _)| k == name)
+            .map(|&(_, ref v)| &v[..])
+    }
+
+    /// The protocol parameters except `realm` and `oauth_signature`.
+    pub fn parameters(&self) -> &[(String, String)] {
+        &self.parameters
+    }
+
+    /// `oauth_consumer_key`
+    pub fn consumer_key(&self) -> &str {
+        self.parameter("oauth_consumer_key").unwrap()
+    }
+
+    /// `oauth_token`, which is absent in two-legged OAuth and Temporary Credential Request.
+    pub fn token(&self) -> Option<&str> {
+        self.parameter("oauth_token")
+    }
+
+    /// `oauth_signature_method`
+    ///
+    /// # Panics
+    /// This function will panic if the signature method is not built-in,
+    /// which is accepted only by `from_header_with_registry`. Use `signature_method_name` for it.
+    pub fn signature_method(&self) -> SignatureMethod {
+        self.signature_method.parse().expect("The signature method is not built-in")
+    }
+
+    /// The name of `oauth_signature_method`, including the ones in `SignerRegistry`.
+    pub fn signature_method_name(&self) -> &str {
+        &self.signature_method
+    }
+
+    /// The decoded `oauth_signature`.
+    pub fn signature(&self) -> &str {
+        &self.signature
+    }
+
+    /// `oauth_timestamp`, which is absent only if the signature method is PLAINTEXT.
+    pub fn timestamp(&self) -> Option<u64> {
+        self.timestamp
+    }
+
+    /// `oauth_nonce`, which is absent only if the signature method is PLAINTEXT.
+    pub fn nonce(&self) -> Option<&str> {
+        self.parameter("oauth_nonce")
+    }
+
+    /// `xoauth_requestor_id` of two-legged OAuth.
+    pub fn requestor_id(&self) -> Option<&str> {
+        self.parameter("xoauth_requestor_id")
+    }
+
+    /// Verifies `oauth_signature`.
+    ///
+    /// `request_parameters` are the decoded parameters of the form-encoded body.
+    /// Pass `None` to `token_secret` if `token()` is `None`.
+    ///
+    /// # Panics
+    /// This function will panic if `url` is not valid for HTTP or HTTPS.
+    pub fn verify<'a, K, V, P>(&self, method: &str, url: &Url, request_parameters: P,
+        consumer_secret: &str, token_secret: Option<&str>) -> Result<(), VerifyError>
+        where K: Into<Cow<'a, str>>, V: Into<Cow<'a, str>>, P: IntoIterator<Item=(K, V)>
+    {
+        let params = request_parameters.into_iter().map(|(k, v)| (str_to_bytes(k.into()), str_to_bytes(v.into())));
+        self.verify_with(&VerifyOptions::new(), method, url, params, consumer_secret, token_secret)
+    }
+
+    /// Verifies `oauth_signature` with the request parameters as byte sequences,
+    /// which are the result of `decode_form_body`.
+    ///
+    /// # Panics
+    /// This function will panic if `url` is not valid for HTTP or HTTPS.
+    pub fn verify_bytes<'a, K, V, P>(&self, method: &str, url: &Url, request_parameters: P,
+        consumer_secret: &str, token_secret: Option<&str>) -> Result<(), VerifyError>
+        where K: Into<Cow<'a, [u8]>>, V: Into<Cow<'a, [u8]>>, P: IntoIterator<Item=(K, V)>
+    {
+        self.verify_with(&VerifyOptions::new(), method, url, request_parameters, consumer_secret, token_secret)
+    }
+
+    /// Verifies `oauth_signature` with `options`.
+    /// The request parameters are the same as `verify_bytes`.
+    ///
+    /// # Panics
+    /// This function will panic if `url` is not valid for HTTP or HTTPS.
+    ///
+    /// # Example
+    /// ```
+    /// # extern crate oauthcli;
+    /// # extern crate url;
+    /// # use oauthcli::*;
+    /// # fn main() {
+    /// let url = url::Url::parse("https://api.twitter.com/1.1/statuses/update.json").unwrap();
+    /// let header =
+    ///     OAuthAuthorizationHeaderBuilder::new(
+    ///         "POST", &url, "consumer", "secret", SignatureMethod::HmacSha1)
+    ///     .request_parameters(vec![("status", "Hello")])
+    ///     .finish_for_twitter();
+    ///
+    /// let req = SignedRequest::from_header(&header).unwrap();
+    /// let params = decode_form_body(b"status=Hello");
+    /// assert!(req.verify_with(VerifyOptions::new().for_twitter(true), "POST", &url, params, "secret", None).is_ok());
+    /// # }
+    /// ```
+    pub fn verify_with<'a, K, V, P>(&self, options: &VerifyOptions, method: &str, url: &Url, request_parameters: P,
+        consumer_secret: &str, token_secret: Option<&str>) -> Result<(), VerifyError>
+        where K: Into<Cow<'a, [u8]>>, V: Into<Cow<'a, [u8]>>, P: IntoIterator<Item=(K, V)>
+    {
+        let signer = match options.registry {
+            Some(registry) => try!(registry.get(&self.signature_method).ok_or(VerifyError::SignatureMethodRejected)),
+            None => try!(self.signature_method.parse::<SignatureMethod>()
+                .map(|x| x.signer() as &Signer)
+                .map_err(|_| VerifyError::SignatureMethodRejected))
+        };
+
+        let params = request_parameters.into_iter().map(|(k, v)| (k.into(), v.into()));
+        self.verify_impl(signer, method, url, params, consumer_secret, token_secret, options.for_twitter)
+    }
+
+    fn verify_impl<'a, 'b: 'a, P>(&'a self, signer: &Signer, method: &str, url: &'a Url, request_parameters: P,
+        consumer_secret: &str, token_secret: Option<&str>, for_twitter: bool) -> Result<(), VerifyError>
+        where P: Iterator<Item=(Cow<'b, [u8]>, Cow<'b, [u8]>)>
+    {
+        #[cfg(feature = "tracing")]
+        let _span = debug_span!("oauth_verify", method = method,
+            signature_method = %self.signature_method, quirks = quirks_profile(for_twitter)).entered();
+
+        let params = self.parameters.iter()
+            .map(|&(ref k, ref v)| (Cow::Borrowed(k.as_bytes()), Cow::Borrowed(v.as_bytes())))
+            .chain(request_parameters.map(|(k, v)| -> (Cow<'a, [u8]>, Cow<'a, [u8]>) { (k, v) }));
+
+        let base_string =
+            if signer.uses_base_string() { signature_base_string(method, url, params, for_twitter) }
+            else { String::new() };
+
+        if signer.verify(&base_string, consumer_secret, token_secret, &self.signature) {
+            Ok(())
+        } else {
+            #[cfg(feature = "tracing")]
+            debug!("The signature does not match");
+            Err(VerifyError::SignatureInvalid)
+        }
+    }
+}